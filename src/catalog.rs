@@ -0,0 +1,246 @@
+use std::{collections::BTreeMap, time::Duration};
+
+use k8s_openapi::api::core::v1::ConfigMap;
+use kube::{
+    api::{Patch, PatchParams, PostParams},
+    core::ObjectMeta,
+    Api,
+};
+use serde_json::json;
+use tracing::{info, warn};
+
+use crate::{
+    common::Cluster,
+    error::Error,
+    operate,
+    types::{CatalogEntry, StorageMode},
+    zeebe,
+};
+
+/// Reads the entries of the catalog, oldest first, leaving out the reservations of backups that
+/// are in progress or were interrupted. A missing catalog is empty.
+pub(crate) async fn read(cluster: &Cluster, name: &str) -> Result<Vec<CatalogEntry>, Error> {
+    let mut entries = read_all(cluster, name).await?;
+    entries.retain(|e| !e.reserved);
+    Ok(entries)
+}
+
+/// Reads all entries of the catalog, oldest first, including reservations. A missing catalog is
+/// empty.
+#[tracing::instrument(skip(cluster), err, level = "debug")]
+pub(crate) async fn read_all(cluster: &Cluster, name: &str) -> Result<Vec<CatalogEntry>, Error> {
+    let configmaps: Api<ConfigMap> = Api::default_namespaced(cluster.kube.clone());
+    let Some(configmap) = configmaps.get_opt(name).await? else {
+        return Ok(vec![]);
+    };
+    let mut entries = configmap
+        .data
+        .unwrap_or_default()
+        .into_iter()
+        .filter_map(|(key, value)| match serde_json::from_str(&value) {
+            Ok(entry) => Some(entry),
+            Err(e) => {
+                warn!(error = %e, "Ignoring invalid catalog entry {}", key);
+                None
+            }
+        })
+        .collect::<Vec<CatalogEntry>>();
+    entries.sort_by_key(|e| e.backup_id);
+    Ok(entries)
+}
+
+/// Adds the entry to the catalog, creating the ConfigMap if it does not exist yet.
+#[tracing::instrument(skip(cluster, entry), fields(backup_id = entry.backup_id), err)]
+pub(crate) async fn write(
+    cluster: &Cluster,
+    name: &str,
+    entry: &CatalogEntry,
+) -> Result<(), Error> {
+    let configmaps: Api<ConfigMap> = Api::default_namespaced(cluster.kube.clone());
+    let key = entry_key(entry.backup_id);
+    let value = serde_json::to_string(entry)?;
+    if configmaps.get_opt(name).await?.is_none() {
+        let configmap = new_catalog(name, &key, &value);
+        match configmaps.create(&PostParams::default(), &configmap).await {
+            Ok(_) => {
+                info!("Created catalog {}", name);
+                return Ok(());
+            }
+            // Created concurrently, add the entry below.
+            Err(kube::Error::Api(e)) if e.code == 409 => {}
+            Err(e) => return Err(e.into()),
+        }
+    }
+    configmaps
+        .patch(
+            name,
+            &PatchParams::default(),
+            &Patch::Merge(&json!({"data": {key: value}})),
+        )
+        .await?;
+    Ok(())
+}
+
+/// Adds a placeholder entry for the backup unless the catalog already has one, so that concurrent
+/// runs of `create` cannot choose the same ID. Returns whether the ID was reserved. The catalog is
+/// replaced with its read `resourceVersion`, so a concurrent change fails the replacement with a
+/// conflict and the check is repeated.
+#[tracing::instrument(skip(cluster), err)]
+pub(crate) async fn reserve(cluster: &Cluster, name: &str, backup_id: u64) -> Result<bool, Error> {
+    let configmaps: Api<ConfigMap> = Api::default_namespaced(cluster.kube.clone());
+    let key = entry_key(backup_id);
+    let value = serde_json::to_string(&CatalogEntry {
+        backup_id,
+        operator: operator(),
+        reserved: true,
+        ..Default::default()
+    })?;
+    loop {
+        let result = match configmaps.get_opt(name).await? {
+            None => {
+                let configmap = new_catalog(name, &key, &value);
+                configmaps.create(&PostParams::default(), &configmap).await
+            }
+            Some(mut configmap) => {
+                let data = configmap.data.get_or_insert_with(BTreeMap::new);
+                if data.contains_key(&key) {
+                    return Ok(false);
+                }
+                data.insert(key.clone(), value.clone());
+                configmaps
+                    .replace(name, &PostParams::default(), &configmap)
+                    .await
+            }
+        };
+        match result {
+            Ok(_) => return Ok(true),
+            // Created or changed concurrently, check again.
+            Err(kube::Error::Api(e)) if e.code == 409 => {}
+            Err(e) => return Err(e.into()),
+        }
+    }
+}
+
+/// Removes the entry of the backup from the catalog, if there is one.
+#[tracing::instrument(skip(cluster), err)]
+pub(crate) async fn remove(cluster: &Cluster, name: &str, backup_id: u64) -> Result<(), Error> {
+    let configmaps: Api<ConfigMap> = Api::default_namespaced(cluster.kube.clone());
+    let patch = json!({"data": {entry_key(backup_id): null}});
+    match configmaps
+        .patch(name, &PatchParams::default(), &Patch::Merge(&patch))
+        .await
+    {
+        Err(kube::Error::Api(e)) if e.code == 404 => Ok(()),
+        result => result.map(|_| ()).map_err(Error::from),
+    }
+}
+
+fn new_catalog(name: &str, key: &str, value: &str) -> ConfigMap {
+    ConfigMap {
+        metadata: ObjectMeta {
+            name: Some(name.to_string()),
+            labels: Some(BTreeMap::from([(
+                "app.kubernetes.io/managed-by".to_string(),
+                "c8-backup".to_string(),
+            )])),
+            ..Default::default()
+        },
+        data: Some(BTreeMap::from([(key.to_string(), value.to_string())])),
+        ..Default::default()
+    }
+}
+
+fn entry_key(backup_id: u64) -> String {
+    format!("{}.json", backup_id)
+}
+
+/// Collects the catalog entry of a completed backup from the components. The components do not
+/// report when the backup was created, so `created_at` is left unset.
+#[tracing::instrument(skip(cluster), err)]
+pub(crate) async fn describe_backup(
+    cluster: &Cluster,
+    storage_mode: StorageMode,
+    backup_id: u64,
+    zeebe_records_snapshot: Option<String>,
+    duration: Duration,
+) -> Result<CatalogEntry, Error> {
+    let mut entry = CatalogEntry {
+        backup_id,
+        zeebe_records_snapshot,
+        duration_seconds: duration.as_secs_f64(),
+        operator: operator(),
+        ..Default::default()
+    };
+    let partitions = match storage_mode {
+        StorageMode::Elasticsearch | StorageMode::Opensearch => {
+            let operate = operate::query_backup(cluster, backup_id).await?;
+            entry.operate_snapshots = operate
+                .details
+                .into_iter()
+                .map(|d| d.snapshot_name)
+                .collect();
+            if let Some(version) = entry
+                .operate_snapshots
+                .first()
+                .and_then(|s| operate_version(s))
+            {
+                entry.versions.insert("operate".to_string(), version);
+            }
+            zeebe::query_backup(cluster, backup_id)
+                .await?
+                .details
+                .into_iter()
+                .map(|d| (d.partition_id, d.checkpoint_position, d.broker_version))
+                .collect::<Vec<_>>()
+        }
+        StorageMode::Rdbms => zeebe::query_runtime_backup(cluster, backup_id)
+            .await?
+            .details
+            .into_iter()
+            .map(|d| {
+                (
+                    Some(d.partition_id),
+                    d.checkpoint_position,
+                    d.broker_version,
+                )
+            })
+            .collect(),
+    };
+    for (partition_id, checkpoint_position, broker_version) in partitions {
+        if let (Some(partition_id), Some(position)) = (partition_id, checkpoint_position) {
+            entry.checkpoint_positions.insert(partition_id, position);
+        }
+        if let Some(version) = broker_version {
+            entry.versions.insert("zeebe".to_string(), version);
+        }
+    }
+    Ok(entry)
+}
+
+/// Operate names its snapshots `camunda_operate_{backup_id}_{version}_part_{n}_of_{count}`.
+fn operate_version(snapshot_name: &str) -> Option<String> {
+    let rest = snapshot_name.strip_prefix("camunda_operate_")?;
+    let (_, rest) = rest.split_once('_')?;
+    let (version, _) = rest.split_once("_part_")?;
+    Some(version.to_string())
+}
+
+fn operator() -> Option<String> {
+    ["USER", "USERNAME", "HOSTNAME"]
+        .iter()
+        .find_map(|var| std::env::var(var).ok().filter(|v| !v.is_empty()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_operate_version() {
+        assert_eq!(
+            operate_version("camunda_operate_1683214620_8.2.0_part_1_of_6").as_deref(),
+            Some("8.2.0")
+        );
+        assert_eq!(operate_version("zeebe-record_1683214620"), None);
+    }
+}
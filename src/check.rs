@@ -0,0 +1,232 @@
+use std::{collections::BTreeSet, time::Duration};
+
+use chrono::{DateTime, Utc};
+
+use crate::{
+    common::Cluster,
+    error::Error,
+    list::{self, find_most_recent_recoverable, find_most_recent_runtime_backup},
+    types::{BackupCheck, BackupList, BackupState, CheckStatus, StorageMode},
+};
+
+#[tracing::instrument(skip(cluster), err)]
+pub(crate) async fn check(
+    cluster: &Cluster,
+    storage_mode: StorageMode,
+    catalog: &str,
+    max_age: Duration,
+) -> Result<BackupCheck, Error> {
+    let backups = list::list(cluster, storage_mode, catalog).await?;
+    Ok(evaluate(&backups, storage_mode, Utc::now(), max_age))
+}
+
+/// Critical if no usable backup is younger than `max_age`, warning if a backup younger than
+/// `max_age` failed or is incomplete in any component or the age of the most recent usable backup
+/// is unknown.
+fn evaluate(
+    backups: &BackupList,
+    storage_mode: StorageMode,
+    now: DateTime<Utc>,
+    max_age: Duration,
+) -> BackupCheck {
+    let most_recent_usable = match storage_mode {
+        StorageMode::Elasticsearch | StorageMode::Opensearch => {
+            find_most_recent_recoverable(&backups.zeebe, &backups.operate, &backups.catalog)
+        }
+        StorageMode::Rdbms => find_most_recent_runtime_backup(&backups.runtime),
+    };
+    let Some(backup_id) = most_recent_usable else {
+        return BackupCheck {
+            status: CheckStatus::Critical,
+            summary: "no usable backup found".to_string(),
+            most_recent_usable,
+        };
+    };
+
+    let age_of = |backup_id: u64| {
+        list::created_at(backup_id, &backups.catalog, now)
+            .map(|created_at| (now - created_at).to_std().unwrap_or_default())
+    };
+    let Some(age) = age_of(backup_id) else {
+        return BackupCheck {
+            status: CheckStatus::Warning,
+            summary: format!(
+                "creation time of most recent usable backup {} is unknown",
+                backup_id
+            ),
+            most_recent_usable,
+        };
+    };
+    // Whole seconds, as the catalog records fractions
+    let age = Duration::from_secs(age.as_secs());
+    let usable = format!(
+        "most recent usable backup {} is {} old",
+        backup_id,
+        humantime::format_duration(age)
+    );
+    if age > max_age {
+        return BackupCheck {
+            status: CheckStatus::Critical,
+            summary: format!(
+                "{}, more than {}",
+                usable,
+                humantime::format_duration(max_age)
+            ),
+            most_recent_usable,
+        };
+    }
+
+    let failed: BTreeSet<u64> = backups
+        .zeebe
+        .iter()
+        .map(|b| (b.backup_id, b.state))
+        .chain(backups.operate.iter().map(|b| (b.backup_id, b.state)))
+        .chain(backups.runtime.iter().map(|b| (b.backup_id, b.state)))
+        .filter(|(id, state)| {
+            matches!(state, BackupState::Failed | BackupState::Incomplete)
+                && age_of(*id).is_some_and(|age| age <= max_age)
+        })
+        .map(|(id, _)| id)
+        .collect();
+    if !failed.is_empty() {
+        let ids = failed.iter().map(u64::to_string).collect::<Vec<_>>();
+        return BackupCheck {
+            status: CheckStatus::Warning,
+            summary: format!(
+                "{}, but backup {} failed or is incomplete",
+                usable,
+                ids.join(", ")
+            ),
+            most_recent_usable,
+        };
+    }
+
+    BackupCheck {
+        status: CheckStatus::Ok,
+        summary: usable,
+        most_recent_usable,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::{BackupDescriptor, CatalogEntry};
+
+    const DAY: Duration = Duration::from_secs(24 * 60 * 60);
+
+    fn backups(zeebe: &[(u64, BackupState)], operate: &[(u64, BackupState)]) -> BackupList {
+        BackupList {
+            zeebe: zeebe
+                .iter()
+                .map(|&(backup_id, state)| BackupDescriptor {
+                    backup_id,
+                    state,
+                    details: vec![],
+                })
+                .collect(),
+            operate: operate
+                .iter()
+                .map(|&(backup_id, state)| BackupDescriptor {
+                    backup_id,
+                    state,
+                    details: vec![],
+                })
+                .collect(),
+            ..Default::default()
+        }
+    }
+
+    fn now() -> DateTime<Utc> {
+        DateTime::from_timestamp(1_700_000_000, 0).unwrap()
+    }
+
+    #[test]
+    fn test_evaluate_ok() {
+        let recent = 1_700_000_000 - 3600;
+        let list = backups(
+            &[(recent, BackupState::Completed)],
+            &[(recent, BackupState::Completed)],
+        );
+        let check = evaluate(&list, StorageMode::Elasticsearch, now(), DAY);
+        assert_eq!(check.status, CheckStatus::Ok);
+        assert_eq!(
+            check.summary,
+            format!("most recent usable backup {} is 1h old", recent)
+        );
+    }
+
+    #[test]
+    fn test_evaluate_stale_or_missing_is_critical() {
+        let old = 1_700_000_000 - 2 * 86400;
+        let list = backups(
+            &[(old, BackupState::Completed)],
+            &[(old, BackupState::Completed)],
+        );
+        let check = evaluate(&list, StorageMode::Elasticsearch, now(), DAY);
+        assert_eq!(check.status, CheckStatus::Critical);
+
+        let check = evaluate(&BackupList::default(), StorageMode::Rdbms, now(), DAY);
+        assert_eq!(check.status, CheckStatus::Critical);
+        assert_eq!(check.most_recent_usable, None);
+    }
+
+    #[test]
+    fn test_evaluate_uses_catalog_for_non_epoch_ids() {
+        let mut list = backups(
+            &[(42, BackupState::Completed)],
+            &[(42, BackupState::Completed)],
+        );
+        let check = evaluate(&list, StorageMode::Elasticsearch, now(), DAY);
+        assert_eq!(check.status, CheckStatus::Warning);
+
+        list.catalog = vec![CatalogEntry {
+            backup_id: 42,
+            created_at: Some("2023-11-14T21:13:20Z".to_string()),
+            ..Default::default()
+        }];
+        let check = evaluate(&list, StorageMode::Elasticsearch, now(), DAY);
+        assert_eq!(check.status, CheckStatus::Ok);
+        assert_eq!(check.summary, "most recent usable backup 42 is 1h old");
+    }
+
+    #[test]
+    fn test_evaluate_accepts_cataloged_operate_snapshots() {
+        let recent = 1_700_000_000 - 3600;
+        let mut list = backups(&[(recent, BackupState::Completed)], &[]);
+        let check = evaluate(&list, StorageMode::Elasticsearch, now(), DAY);
+        assert_eq!(check.status, CheckStatus::Critical);
+
+        list.catalog = vec![CatalogEntry {
+            backup_id: recent,
+            operate_snapshots: vec![format!("camunda_operate_{}_8.5.0_part_1_of_1", recent)],
+            ..Default::default()
+        }];
+        let check = evaluate(&list, StorageMode::Elasticsearch, now(), DAY);
+        assert_eq!(check.status, CheckStatus::Ok);
+        assert_eq!(check.most_recent_usable, Some(recent));
+    }
+
+    #[test]
+    fn test_evaluate_recent_failure_is_warning() {
+        let usable = 1_700_000_000 - 7200;
+        let failed = 1_700_000_000 - 3600;
+        let old_failure = 1_700_000_000 - 2 * 86400;
+        let list = backups(
+            &[
+                (old_failure, BackupState::Failed),
+                (usable, BackupState::Completed),
+                (failed, BackupState::Completed),
+            ],
+            &[
+                (usable, BackupState::Completed),
+                (failed, BackupState::Incomplete),
+            ],
+        );
+        let check = evaluate(&list, StorageMode::Elasticsearch, now(), DAY);
+        assert_eq!(check.status, CheckStatus::Warning);
+        assert!(check
+            .summary
+            .ends_with(&format!("but backup {} failed or is incomplete", failed)));
+    }
+}
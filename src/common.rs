@@ -1,51 +1,491 @@
+use std::{
+    collections::HashMap,
+    future::Future,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
 use bytes::Bytes;
 use http_body_util::{BodyExt, Full};
-use hyper::Request;
+use hyper::{
+    body::Incoming, client::conn::http1::SendRequest, header::HOST, Method, Request, Response,
+};
 use hyper_util::rt::TokioIo;
 use k8s_openapi::api::core::v1::Pod;
 use kube::{api::ListParams, Api};
-use tracing::{debug, error};
+use tokio::{
+    io::{AsyncRead, AsyncWrite},
+    time::sleep,
+};
+use tracing::{debug, warn};
+
+use crate::error::Error;
+use crate::types::{ComponentSettings, RetryArgs};
+
+type Sender = SendRequest<Full<Bytes>>;
+
+/// Connection pools of the port-forwarded components, keyed by namespace, label selector and
+/// port so that clients of different namespaces never share a port-forward.
+#[derive(Default)]
+struct ComponentPools(Mutex<HashMap<(String, String, u16), Arc<ConnectionPool>>>);
+
+impl ComponentPools {
+    fn get(
+        &self,
+        namespace: &str,
+        selector: &str,
+        port: u16,
+        retry: RetryPolicy,
+    ) -> Arc<ConnectionPool> {
+        self.0
+            .lock()
+            .expect("Component pools must not be poisoned")
+            .entry((namespace.to_string(), selector.to_string(), port))
+            .or_insert_with(|| Arc::new(ConnectionPool::new("127.0.0.1", retry)))
+            .clone()
+    }
+}
+
+/// The Kubernetes client of one deployment together with where its components are found, how
+/// requests to them are retried and the pooled connections to them.
+#[derive(Clone)]
+pub(crate) struct Cluster {
+    pub kube: kube::Client,
+    pub settings: Arc<ComponentSettings>,
+    pub retry: RetryPolicy,
+    pools: Arc<ComponentPools>,
+}
+
+impl Cluster {
+    pub fn new(kube: kube::Client, settings: ComponentSettings, retry: RetryPolicy) -> Self {
+        Self {
+            kube,
+            settings: Arc::new(settings),
+            retry,
+            pools: Arc::default(),
+        }
+    }
+}
+
+/// How often and how fast failed requests are retried.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RetryPolicy {
+    pub max_attempts: u32,
+    pub initial_backoff: Duration,
+    pub max_backoff: Duration,
+}
 
-#[tracing::instrument(skip(kube), err, level = "debug")]
-pub async fn make_component_request(
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_attempts: 5,
+            initial_backoff: Duration::from_millis(500),
+            max_backoff: Duration::from_secs(30),
+        }
+    }
+}
+
+impl From<&RetryArgs> for RetryPolicy {
+    fn from(args: &RetryArgs) -> Self {
+        Self {
+            max_attempts: args.retry_attempts.get(),
+            initial_backoff: Duration::from_millis(args.retry_initial_backoff_ms),
+            max_backoff: Duration::from_millis(args.retry_max_backoff_ms),
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// Delay before the given retry, doubling with every attempt. `jitter` between 0 and 1
+    /// spreads the delay over the upper half so that concurrent requests do not retry in lockstep.
+    fn backoff(&self, attempt: u32, jitter: f64) -> Duration {
+        let exponential = self
+            .initial_backoff
+            .saturating_mul(2u32.saturating_pow(attempt.saturating_sub(1)));
+        let capped = exponential.min(self.max_backoff);
+        capped.mul_f64(0.5 + jitter.clamp(0.0, 1.0) / 2.0)
+    }
+}
+
+/// Marks a POST request as safe to retry, for example `.extension(Idempotent)`.
+#[derive(Debug, Clone, Copy)]
+pub struct Idempotent;
+
+#[tracing::instrument(skip(cluster), err, level = "debug")]
+pub(crate) async fn make_component_request(
+    cluster: &Cluster,
+    component: &str,
+    port: u16,
+    req: Request<Full<Bytes>>,
+) -> Result<Bytes, Error> {
+    let pool = cluster.pools.get(
+        cluster.kube.default_namespace(),
+        component,
+        port,
+        cluster.retry,
+    );
+    pool.request(req, |failed| {
+        connect_component(&cluster.kube, component, port, failed)
+    })
+    .await
+}
+
+/// Connects to a ready pod of the component, starting with the replica after the one that
+/// `failed` last and failing over to the other replicas if the port-forward or handshake fails.
+async fn connect_component(
     kube: &kube::Client,
     component: &str,
     port: u16,
-    mut req: Request<Full<Bytes>>,
-) -> Result<Bytes, Box<dyn std::error::Error>> {
+    failed: Option<String>,
+) -> Result<(Sender, String), Error> {
     let pods = Api::<Pod>::default_namespaced(kube.clone());
-    let pod = pods
+    let ready = pods
         .list(&ListParams::default().labels(component))
         .await?
         .items
-        .first()
-        .expect(&format!("Pod with label {component} must exist"))
-        .clone();
+        .into_iter()
+        .filter(is_ready)
+        .filter_map(|pod| pod.metadata.name)
+        .collect::<Vec<_>>();
+    let ready = failover_order(ready, failed.as_deref());
+    if ready.is_empty() {
+        return Err(Error::ComponentUnreachable(format!(
+            "No ready pod with label {} found",
+            component
+        )));
+    }
+
+    let mut last_error = None;
+    for name in ready {
+        debug!("Port-forwarding to {}:{}", name, port);
+        match connect_pod(&pods, &name, port).await {
+            Ok(sender) => return Ok((sender, name)),
+            Err(e) => {
+                warn!(
+                    "Failed to connect to {}:{}, trying next pod: {}",
+                    name, port, e
+                );
+                last_error = Some(e);
+            }
+        }
+    }
+    Err(last_error.expect("At least one pod must have been tried"))
+}
+
+async fn connect_pod(pods: &Api<Pod>, name: &str, port: u16) -> Result<Sender, Error> {
     let forwarded_port = pods
-        .portforward(&pod.metadata.name.expect("Pod must have a name"), &[port])
+        .portforward(name, &[port])
         .await?
         .take_stream(port)
-        .expect(&format!("Port {port} must be open"));
+        .ok_or(format!("Port {} of {} is not forwarded", port, name))?;
+    connect(forwarded_port).await
+}
+
+/// Orders the pods so that the ones after the `failed` pod come first and the failed one last.
+fn failover_order(mut pods: Vec<String>, failed: Option<&str>) -> Vec<String> {
+    if let Some(position) = pods.iter().position(|pod| Some(pod.as_str()) == failed) {
+        pods.rotate_left(position + 1);
+    }
+    pods
+}
+
+/// Whether the pod is running, ready and not terminating.
+fn is_ready(pod: &Pod) -> bool {
+    let Some(status) = &pod.status else {
+        return false;
+    };
+    pod.metadata.deletion_timestamp.is_none()
+        && status.phase.as_deref() == Some("Running")
+        && status
+            .conditions
+            .iter()
+            .flatten()
+            .any(|c| c.type_ == "Ready" && c.status == "True")
+}
 
-    let io = TokioIo::new(forwarded_port);
-    let (mut sender, connection) = hyper::client::conn::http1::handshake(io).await?;
+/// Performs the HTTP/1 handshake over an established stream.
+pub async fn connect<T>(io: T) -> Result<Sender, Error>
+where
+    T: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+{
+    let io = TokioIo::new(io);
+    let (sender, connection) = hyper::client::conn::http1::handshake(io).await?;
     tokio::spawn(async move {
         if let Err(e) = connection.await {
-            error!("Failed to establish connection: {}", e);
-        } else {
-            debug!("Connection established");
+            debug!("Connection closed: {}", e);
         }
     });
+    Ok(sender)
+}
+
+/// Idle HTTP/1 connections to a single destination that are reused by later requests.
+/// Connections that were closed in the meantime, for example because the pod went away, are
+/// replaced transparently.
+///
+/// Each connection leads to a peer, such as one replica of a component. When a request to a peer
+/// fails with a transient error, its idle connections are dropped and the peer is passed to
+/// `connect` so that the retry goes to another replica.
+pub struct ConnectionPool {
+    host: String,
+    retry: RetryPolicy,
+    idle: Mutex<Vec<(Sender, String)>>,
+    failed: Mutex<Option<String>>,
+}
+
+impl ConnectionPool {
+    pub fn new(host: impl Into<String>, retry: RetryPolicy) -> Self {
+        Self {
+            host: host.into(),
+            retry,
+            idle: Mutex::default(),
+            failed: Mutex::default(),
+        }
+    }
+
+    /// Sends the request over an idle connection or a new one from `connect` and returns the
+    /// response body. `connect` receives the peer that failed last and returns the new
+    /// connection together with its peer. Idempotent requests that fail with a connection error
+    /// or a transient status are retried according to the retry policy of the pool.
+    pub async fn request<F, Fut>(
+        &self,
+        mut req: Request<Full<Bytes>>,
+        connect: F,
+    ) -> Result<Bytes, Error>
+    where
+        F: Fn(Option<String>) -> Fut,
+        Fut: Future<Output = Result<(Sender, String), Error>>,
+    {
+        req.headers_mut().insert(HOST, self.host.parse()?);
+        let policy = self.retry;
+        if !is_retryable_request(&req) {
+            return self.send(req, &connect).await;
+        }
+
+        let mut attempt = 1;
+        loop {
+            match self.send(clone_request(&req), &connect).await {
+                Err(e) if attempt < policy.max_attempts && e.is_transient() => {
+                    let delay = policy.backoff(attempt, rand::random());
+                    warn!(
+                        "{} {} failed, retrying in {:?} (attempt {}/{}): {}",
+                        req.method(),
+                        req.uri(),
+                        delay,
+                        attempt,
+                        policy.max_attempts,
+                        e
+                    );
+                    sleep(delay).await;
+                    attempt += 1;
+                }
+                result => return result,
+            }
+        }
+    }
 
-    req.headers_mut()
-        .append("Host", "127.0.0.1".parse().unwrap());
+    /// Sends the request once. A request that could not be sent over an idle connection is
+    /// retried on another one.
+    async fn send<F, Fut>(&self, mut req: Request<Full<Bytes>>, connect: &F) -> Result<Bytes, Error>
+    where
+        F: Fn(Option<String>) -> Fut,
+        Fut: Future<Output = Result<(Sender, String), Error>>,
+    {
+        loop {
+            let (mut sender, peer, reused) = match self.take_idle() {
+                Some((sender, peer)) => (sender, peer, true),
+                None => {
+                    let (sender, peer) = connect(self.failed_peer()).await?;
+                    (sender, peer, false)
+                }
+            };
+            let result = match sender.try_send_request(req).await {
+                Ok(resp) => self.read_response(sender, &peer, resp).await,
+                Err(mut e) => match e.take_message() {
+                    Some(message) if reused => {
+                        debug!("Idle connection was closed, retrying: {}", e.error());
+                        req = message;
+                        continue;
+                    }
+                    _ => Err(e.into_error().into()),
+                },
+            };
+            if matches!(&result, Err(e) if e.is_transient()) {
+                self.mark_failed(peer);
+            }
+            return result;
+        }
+    }
+
+    fn take_idle(&self) -> Option<(Sender, String)> {
+        let mut idle = self
+            .idle
+            .lock()
+            .expect("Connection pool must not be poisoned");
+        while let Some((sender, peer)) = idle.pop() {
+            if !sender.is_closed() {
+                return Some((sender, peer));
+            }
+        }
+        None
+    }
 
-    let mut resp = sender.send_request(req).await?;
-    if !resp.status().is_success() {
+    fn failed_peer(&self) -> Option<String> {
+        self.failed
+            .lock()
+            .expect("Connection pool must not be poisoned")
+            .clone()
+    }
+
+    /// Drops the idle connections to the peer and avoids it for new connections.
+    fn mark_failed(&self, peer: String) {
+        self.idle
+            .lock()
+            .expect("Connection pool must not be poisoned")
+            .retain(|(_, p)| *p != peer);
+        *self
+            .failed
+            .lock()
+            .expect("Connection pool must not be poisoned") = Some(peer);
+    }
+
+    async fn read_response(
+        &self,
+        sender: Sender,
+        peer: &str,
+        mut resp: Response<Incoming>,
+    ) -> Result<Bytes, Error> {
         let body = resp.body_mut().collect().await.map(|c| c.to_bytes());
-        error!("Request failed: {:?}, {:?}", resp, body);
-        return Err("Request failed".into());
+        if body.is_ok() && !sender.is_closed() {
+            self.idle
+                .lock()
+                .expect("Connection pool must not be poisoned")
+                .push((sender, peer.to_string()));
+        }
+        let body = body?;
+        if !resp.status().is_success() {
+            return Err(Error::Http {
+                status: resp.status(),
+                body: String::from_utf8_lossy(&body).into_owned(),
+            });
+        }
+        Ok(body)
+    }
+}
+
+fn is_retryable_request(req: &Request<Full<Bytes>>) -> bool {
+    matches!(
+        *req.method(),
+        Method::GET | Method::HEAD | Method::PUT | Method::DELETE | Method::OPTIONS
+    ) || req.extensions().get::<Idempotent>().is_some()
+}
+
+fn clone_request(req: &Request<Full<Bytes>>) -> Request<Full<Bytes>> {
+    let mut clone = Request::new(req.body().clone());
+    *clone.method_mut() = req.method().clone();
+    *clone.uri_mut() = req.uri().clone();
+    *clone.version_mut() = req.version();
+    *clone.headers_mut() = req.headers().clone();
+    *clone.extensions_mut() = req.extensions().clone();
+    clone
+}
+
+#[cfg(test)]
+mod tests {
+    use k8s_openapi::{
+        api::core::v1::{PodCondition, PodStatus},
+        apimachinery::pkg::apis::meta::v1::Time,
+    };
+    use kube::core::ObjectMeta;
+
+    use super::*;
+
+    fn pod(phase: &str, ready: &str) -> Pod {
+        Pod {
+            status: Some(PodStatus {
+                phase: Some(phase.into()),
+                conditions: Some(vec![PodCondition {
+                    type_: "Ready".into(),
+                    status: ready.into(),
+                    ..Default::default()
+                }]),
+                ..Default::default()
+            }),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_backoff() {
+        let policy = RetryPolicy {
+            max_attempts: 5,
+            initial_backoff: Duration::from_millis(100),
+            max_backoff: Duration::from_millis(500),
+        };
+        assert_eq!(policy.backoff(1, 1.0), Duration::from_millis(100));
+        assert_eq!(policy.backoff(2, 1.0), Duration::from_millis(200));
+        assert_eq!(policy.backoff(3, 0.0), Duration::from_millis(200));
+        assert_eq!(policy.backoff(4, 1.0), Duration::from_millis(500));
+        assert_eq!(policy.backoff(40, 1.0), Duration::from_millis(500));
+    }
+
+    #[test]
+    fn test_is_retryable_request() {
+        let get = Request::get("/actuator/backups")
+            .body(Full::default())
+            .unwrap();
+        let post = Request::post("/actuator/backups")
+            .body(Full::default())
+            .unwrap();
+        let pause = Request::post("/actuator/exporting/pause")
+            .extension(Idempotent)
+            .body(Full::default())
+            .unwrap();
+        assert!(is_retryable_request(&get));
+        assert!(!is_retryable_request(&post));
+        assert!(is_retryable_request(&pause));
+        assert!(is_retryable_request(&clone_request(&pause)));
+    }
+
+    #[test]
+    fn test_component_pools_are_per_namespace() {
+        let pools = ComponentPools::default();
+        let selector = "app.kubernetes.io/component=zeebe-gateway";
+        let get = |namespace, port| pools.get(namespace, selector, port, RetryPolicy::default());
+        let a = get("team-a", 9600);
+        assert!(Arc::ptr_eq(&a, &get("team-a", 9600)));
+        assert!(!Arc::ptr_eq(&a, &get("team-b", 9600)));
+        assert!(!Arc::ptr_eq(&a, &get("team-a", 8080)));
+    }
+
+    #[test]
+    fn test_failover_order() {
+        let pods = || vec!["zeebe-0".to_string(), "zeebe-1".into(), "zeebe-2".into()];
+        assert_eq!(failover_order(pods(), None), pods());
+        assert_eq!(failover_order(pods(), Some("zeebe-3")), pods());
+        assert_eq!(
+            failover_order(pods(), Some("zeebe-0")),
+            vec!["zeebe-1", "zeebe-2", "zeebe-0"]
+        );
+        assert_eq!(
+            failover_order(pods(), Some("zeebe-2")),
+            vec!["zeebe-0", "zeebe-1", "zeebe-2"]
+        );
+    }
+
+    #[test]
+    fn test_is_ready() {
+        assert!(is_ready(&pod("Running", "True")));
+        assert!(!is_ready(&pod("Running", "False")));
+        assert!(!is_ready(&pod("Pending", "False")));
+        assert!(!is_ready(&Pod::default()));
+
+        let terminating = Pod {
+            metadata: ObjectMeta {
+                deletion_timestamp: Some(Time(chrono::Utc::now())),
+                ..Default::default()
+            },
+            ..pod("Running", "True")
+        };
+        assert!(!is_ready(&terminating));
     }
-    let body = resp.body_mut().collect().await?.to_bytes();
-    Ok(body)
 }
@@ -1,143 +1,653 @@
-use std::{error::Error, time::Duration};
+use std::{collections::BTreeSet, time::Duration};
 
-use chrono::Utc;
-use tokio::time::sleep;
+use chrono::{DateTime, Utc};
+use humantime::format_duration;
+use k8s_openapi::api::apps::v1::{Deployment, StatefulSet};
+use kube::{runtime::events::EventType, ResourceExt};
+use serde_json::{json, Value};
+use tokio::time::{sleep, Instant};
 use tracing::{info, warn};
 
 use crate::{
-    elasticsearch::{take_snapshot, SnapshotRequest},
+    catalog,
+    common::Cluster,
+    elasticsearch::{get_index_settings, get_snapshot, take_snapshot, Elasticsearch, SearchClient},
+    error::Error,
+    events,
+    opensearch::Opensearch,
     operate,
-    types::{BackupDescriptor, BackupState, StorageMode},
+    prefixes::IndexPrefixes,
+    settings, templates,
+    types::{
+        BackupDescriptor, BackupIdStrategy, BackupState, CatalogEntry, CreateArgs, CreatedBackup,
+        IndexPrefixArgs, SearchArgs, StorageMode,
+    },
     zeebe,
 };
 
-#[tracing::instrument(err)]
-pub(crate) async fn create(storage_mode: StorageMode) -> Result<(), Box<dyn Error>> {
-    let kube = kube::Client::try_default().await?;
-    let backup_id = Utc::now().timestamp() as u64;
+#[tracing::instrument(skip(cluster, search_args), err)]
+pub(crate) async fn create(
+    cluster: &Cluster,
+    storage_mode: StorageMode,
+    search_args: &SearchArgs,
+    index_prefixes: &IndexPrefixArgs,
+    catalog: &str,
+    create_args: &CreateArgs,
+) -> Result<CreatedBackup, Error> {
+    let started = Instant::now();
+    let backup_id = allocate_backup_id(cluster, storage_mode, catalog, create_args).await?;
+    // Reserving the ID in the catalog before triggering any component keeps concurrent runs from
+    // taking two backups with the same ID.
+    match catalog::reserve(cluster, catalog, backup_id).await {
+        Ok(true) => {}
+        Ok(false) => {
+            return Err(format!(
+                "Backup ID {} was reserved concurrently by another backup",
+                backup_id
+            )
+            .into())
+        }
+        Err(e) => {
+            warn!(error = %e, "Could not reserve backup ID {} in catalog {}", backup_id, catalog)
+        }
+    }
+
+    let result = take_backup(
+        cluster,
+        storage_mode,
+        search_args,
+        index_prefixes,
+        create_args,
+        backup_id,
+    )
+    .await;
+    record_outcome(cluster, backup_id, result.as_ref().err()).await;
+    if result.is_err() {
+        if let Err(e) = catalog::remove(cluster, catalog, backup_id).await {
+            warn!(error = %e, "Could not release backup ID {} in catalog {}", backup_id, catalog);
+        }
+    }
+    let (zeebe_records_snapshot, exporting_paused) = result?;
+    let backup = CreatedBackup {
+        backup_id,
+        zeebe_records_snapshot,
+        duration: started.elapsed(),
+        exporting_paused,
+    };
 
-    match storage_mode {
-        StorageMode::Elasticsearch => create_es(&kube, backup_id).await,
-        StorageMode::Rdbms => create_rdbms(&kube, backup_id).await,
+    // The backup is usable without its catalog entry, so failing to record it is not fatal.
+    let recorded = match catalog::describe_backup(
+        cluster,
+        storage_mode,
+        backup_id,
+        backup.zeebe_records_snapshot.clone(),
+        backup.duration,
+    )
+    .await
+    {
+        Ok(entry) => {
+            let entry = CatalogEntry {
+                created_at: Some(Utc::now().to_rfc3339()),
+                labels: create_args.labels.iter().cloned().collect(),
+                note: create_args.note.clone(),
+                pinned: create_args.pin,
+                ..entry
+            };
+            catalog::write(cluster, catalog, &entry).await
+        }
+        Err(e) => Err(e),
+    };
+    if let Err(e) = recorded {
+        warn!(error = %e, "Could not record backup {} in catalog {}", backup_id, catalog);
     }
+
+    Ok(backup)
 }
 
-#[tracing::instrument(skip(kube), err)]
-async fn create_es(kube: &kube::Client, backup_id: u64) -> Result<(), Box<dyn Error>> {
-    let result = try_backup(kube, backup_id).await;
+/// Chooses the ID of the new backup and makes sure that no component and no catalog entry uses it
+/// yet, as reusing an ID would mix up the parts of two backups.
+#[tracing::instrument(skip(cluster, create_args), err)]
+async fn allocate_backup_id(
+    cluster: &Cluster,
+    storage_mode: StorageMode,
+    catalog: &str,
+    create_args: &CreateArgs,
+) -> Result<u64, Error> {
+    let mut used: BTreeSet<u64> = match storage_mode {
+        StorageMode::Elasticsearch | StorageMode::Opensearch => zeebe::list_backups(cluster)
+            .await?
+            .into_iter()
+            .map(|b| b.backup_id)
+            .chain(
+                operate::list_backups(cluster)
+                    .await?
+                    .into_iter()
+                    .map(|b| b.backup_id),
+            )
+            .collect(),
+        StorageMode::Rdbms => zeebe::list_runtime_backups(cluster)
+            .await?
+            .into_iter()
+            .map(|b| b.backup_id)
+            .collect(),
+    };
+    match catalog::read_all(cluster, catalog).await {
+        Ok(entries) => used.extend(entries.iter().map(|e| e.backup_id)),
+        Err(e) => warn!("Could not read the backup catalog {}: {}", catalog, e),
+    }
+
+    let backup_id = match create_args.backup_id {
+        Some(backup_id) => backup_id,
+        None => next_backup_id(
+            create_args.backup_id_strategy,
+            Utc::now(),
+            used.last().copied(),
+        ),
+    };
+    check_backup_id(backup_id, &used)?;
+    Ok(backup_id)
+}
+
+/// Refuses IDs that are already `used`. The most recent backup is the one with the highest ID, see
+/// [`crate::list::find_most_recent_usable`], so a lower ID is accepted with a warning.
+fn check_backup_id(backup_id: u64, used: &BTreeSet<u64>) -> Result<(), Error> {
+    if used.contains(&backup_id) {
+        return Err(format!(
+            "Backup ID {} is already used, choose another one with --backup-id or \
+             --backup-id-strategy",
+            backup_id
+        )
+        .into());
+    }
+    if let Some(highest) = used.last().filter(|highest| **highest > backup_id) {
+        warn!(
+            "Backup ID {} is lower than the highest used ID {}, so the backup will not be the \
+             most recent one",
+            backup_id, highest
+        );
+    }
+    Ok(())
+}
+
+fn next_backup_id(strategy: BackupIdStrategy, now: DateTime<Utc>, highest: Option<u64>) -> u64 {
+    let seconds = now.timestamp() as u64;
+    match strategy {
+        BackupIdStrategy::Seconds => seconds,
+        BackupIdStrategy::Millis => now.timestamp_millis() as u64,
+        BackupIdStrategy::Monotonic => {
+            highest.map_or(seconds, |highest| (highest + 1).max(seconds))
+        }
+    }
+}
+
+/// Returns the snapshot of exported Zeebe records and how long exporting was paused.
+async fn take_backup(
+    cluster: &Cluster,
+    storage_mode: StorageMode,
+    search_args: &SearchArgs,
+    index_prefixes: &IndexPrefixArgs,
+    create_args: &CreateArgs,
+    backup_id: u64,
+) -> Result<(Option<String>, Option<Duration>), Error> {
+    Ok(match storage_mode {
+        StorageMode::Elasticsearch => {
+            let prefixes = IndexPrefixes::discover(cluster, index_prefixes).await?;
+            let search = Elasticsearch::new(cluster, search_args).await?;
+            let paused = create_es(cluster, &search, &prefixes, create_args, backup_id).await?;
+            (
+                Some(prefixes.zeebe_records_snapshot(backup_id)),
+                Some(paused),
+            )
+        }
+        StorageMode::Opensearch => {
+            let prefixes = IndexPrefixes::discover(cluster, index_prefixes).await?;
+            let search = Opensearch::new(cluster, search_args).await?;
+            let paused = create_es(cluster, &search, &prefixes, create_args, backup_id).await?;
+            (
+                Some(prefixes.zeebe_records_snapshot(backup_id)),
+                Some(paused),
+            )
+        }
+        StorageMode::Rdbms => {
+            create_rdbms(cluster, backup_id, create_args.backup_timeout).await?;
+            (None, None)
+        }
+    })
+}
+
+/// Explains the backup in the events and annotations of the Zeebe brokers and Operate.
+async fn record_outcome(cluster: &Cluster, backup_id: u64, error: Option<&Error>) {
+    let (type_, reason, note) = match error {
+        None => (
+            EventType::Normal,
+            "BackupCreated",
+            format!("Backup {} completed", backup_id),
+        ),
+        Some(e) => (
+            EventType::Warning,
+            "BackupFailed",
+            format!("Backup {} failed: {}", backup_id, e),
+        ),
+    };
+    let backup_id = backup_id.to_string();
+    let settings = &cluster.settings;
+    for object in events::objects::<StatefulSet>(cluster, &settings.zeebe_broker_selector).await {
+        events::publish(cluster, &object, type_, "Backup", reason, note.clone()).await;
+        if error.is_none() {
+            events::annotate::<StatefulSet>(
+                cluster,
+                &object.name_any(),
+                events::LAST_BACKUP_ID,
+                Some(&backup_id),
+            )
+            .await;
+        }
+    }
+    for object in events::objects::<Deployment>(cluster, &settings.operate_selector).await {
+        events::publish(cluster, &object, type_, "Backup", reason, note.clone()).await;
+        if error.is_none() {
+            events::annotate::<Deployment>(
+                cluster,
+                &object.name_any(),
+                events::LAST_BACKUP_ID,
+                Some(&backup_id),
+            )
+            .await;
+        }
+    }
+}
+
+/// Returns how long Zeebe exporting was paused.
+#[tracing::instrument(skip(cluster, search, prefixes), err)]
+async fn create_es(
+    cluster: &Cluster,
+    search: &impl SearchClient,
+    prefixes: &IndexPrefixes,
+    create_args: &CreateArgs,
+    backup_id: u64,
+) -> Result<Duration, Error> {
+    // Zeebe and Operate do not know the ID, but a snapshot may be left over from a failed backup.
+    let name = prefixes.zeebe_records_snapshot(backup_id);
+    match get_snapshot(search, &name).await {
+        Ok(_) => {
+            return Err(format!(
+                "Backup ID {} is already used by snapshot {}",
+                backup_id, name
+            )
+            .into())
+        }
+        Err(e) if e.is_not_found() => {}
+        Err(e) => return Err(e),
+    }
+
+    // Exporting must be resumed if the user interrupts the backup, so Ctrl-C is handled here
+    // instead of terminating the process.
+    let result = tokio::select! {
+        result = try_backup(cluster, search, prefixes, create_args, backup_id) => result,
+        _ = tokio::signal::ctrl_c() => Err(Error::Aborted),
+    };
     match result {
         Err(e) => {
-            warn!(e, "Backup failed, trying to resume Zeebe exporting");
-            zeebe::resume_exporting(kube).await?;
+            warn!(error = %e, "Backup failed, trying to resume Zeebe exporting");
+            zeebe::resume_exporting(cluster).await?;
             Err(e)
         }
         _ => result,
     }
 }
 
-#[tracing::instrument(skip(kube), err)]
-async fn create_rdbms(kube: &kube::Client, backup_id: u64) -> Result<(), Box<dyn Error>> {
+#[tracing::instrument(skip(cluster), err)]
+async fn create_rdbms(cluster: &Cluster, backup_id: u64, timeout: Duration) -> Result<(), Error> {
     info!("Triggering runtime backup {}", backup_id);
-    zeebe::take_runtime_backup(kube, backup_id).await?;
+    zeebe::take_runtime_backup(cluster, backup_id).await?;
 
     info!("Waiting for runtime backup to complete...");
+    let poll_interval = cluster.settings.poll_interval;
+    let start = Instant::now();
     loop {
-        match zeebe::query_runtime_backup(kube, backup_id).await {
+        match zeebe::query_runtime_backup(cluster, backup_id).await {
             Ok(backup) if backup.state == BackupState::Completed => {
                 info!("Runtime backup {} completed", backup_id);
                 return Ok(());
             }
             Ok(backup) if backup.state == BackupState::Failed => {
                 let reason = backup.failure_reason.unwrap_or_default();
-                return Err(format!("Runtime backup {} failed: {}", backup_id, reason).into());
+                return Err(Error::BackupFailed(format!(
+                    "Runtime backup {} failed: {}",
+                    backup_id, reason
+                )));
             }
             Ok(backup) => {
-                info!("Checking again in 5 seconds, state is {:?}", backup.state);
-                sleep(Duration::from_secs(5)).await;
+                info!(
+                    "Checking again in {}, state is {:?}",
+                    format_duration(poll_interval),
+                    backup.state
+                );
             }
             Err(e) => {
-                info!("Checking again in 5 seconds, error: {}", e);
-                sleep(Duration::from_secs(5)).await;
+                info!(
+                    "Checking again in {}, error: {}",
+                    format_duration(poll_interval),
+                    e
+                );
             }
         }
+        if start.elapsed() >= timeout {
+            return Err(Error::Timeout(format!(
+                "Runtime backup {} did not complete within {}",
+                backup_id,
+                format_duration(timeout)
+            )));
+        }
+        sleep(poll_interval).await;
     }
 }
 
-#[tracing::instrument(skip(kube), err)]
-async fn try_backup(kube: &kube::Client, backup_id: u64) -> Result<(), Box<dyn Error>> {
-    backup_operate(kube, backup_id).await?;
-    zeebe::pause_exporting(kube).await?;
-    backup_zeebe_export(kube, backup_id).await?;
-    backup_zeebe(kube, backup_id).await?;
-    zeebe::resume_exporting(kube).await?;
-    Ok(())
+#[tracing::instrument(skip(cluster, search, prefixes), err)]
+async fn try_backup(
+    cluster: &Cluster,
+    search: &impl SearchClient,
+    prefixes: &IndexPrefixes,
+    create_args: &CreateArgs,
+    backup_id: u64,
+) -> Result<Duration, Error> {
+    backup_operate(cluster, backup_id, create_args.backup_timeout).await?;
+    zeebe::pause_exporting(cluster).await?;
+    let paused = Instant::now();
+    backup_zeebe_export(search, prefixes, create_args, backup_id).await?;
+    backup_zeebe(cluster, backup_id, create_args.backup_timeout).await?;
+    zeebe::resume_exporting(cluster).await?;
+    Ok(paused.elapsed())
+}
+
+#[tracing::instrument(skip(cluster), err)]
+async fn backup_operate(cluster: &Cluster, backup_id: u64, timeout: Duration) -> Result<(), Error> {
+    operate::take_backup(cluster, backup_id).await?;
+    info!("Started backup");
+    wait_for_backup(
+        "Operate",
+        backup_id,
+        timeout,
+        cluster.settings.poll_interval,
+        || operate::query_backup(cluster, backup_id),
+    )
+    .await
 }
 
-#[tracing::instrument(skip(kube), err)]
-async fn backup_operate(kube: &kube::Client, backup_id: u64) -> Result<(), Box<dyn Error>> {
-    operate::take_backup(kube, backup_id).await?;
+#[tracing::instrument(skip(search, prefixes), err)]
+async fn backup_zeebe_export(
+    search: &impl SearchClient,
+    prefixes: &IndexPrefixes,
+    create_args: &CreateArgs,
+    backup_id: u64,
+) -> Result<(), Error> {
+    let name = prefixes.zeebe_records_snapshot(backup_id);
+    let templates_index = prefixes.templates_index();
+    let owned_prefixes = prefixes.owned_prefixes();
+    let index_settings = get_index_settings(search, settings::RECORDED_SETTINGS).await?;
+    templates::capture_templates(
+        search,
+        &owned_prefixes,
+        &templates_index,
+        settings::stored_settings(&index_settings, &owned_prefixes),
+    )
+    .await?;
+    let metadata = with_create_args(json!({}), create_args);
+    take_snapshot(
+        search,
+        &format!("{},{}", prefixes.zeebe_records_pattern(), templates_index),
+        &name,
+        Some(metadata),
+    )
+    .await?;
 
+    Ok(())
+}
+
+#[tracing::instrument(skip(cluster), err)]
+async fn backup_zeebe(cluster: &Cluster, backup_id: u64, timeout: Duration) -> Result<(), Error> {
+    zeebe::take_backup(cluster, backup_id).await?;
     info!("Started backup");
+    wait_for_backup(
+        "Zeebe",
+        backup_id,
+        timeout,
+        cluster.settings.poll_interval,
+        || zeebe::query_backup(cluster, backup_id),
+    )
+    .await
+}
+
+/// Polls the backup of a component until it is completed. Fails with [`Error::BackupFailed`] once
+/// the component reports a state that the backup cannot recover from and with [`Error::Timeout`]
+/// if it is not completed within `timeout`.
+async fn wait_for_backup<T, F, Fut>(
+    component: &str,
+    backup_id: u64,
+    timeout: Duration,
+    poll_interval: Duration,
+    query: F,
+) -> Result<(), Error>
+where
+    F: Fn() -> Fut,
+    Fut: std::future::Future<Output = Result<BackupDescriptor<T>, Error>>,
+{
+    let start = Instant::now();
     loop {
-        match operate::query_backup(kube, backup_id).await {
-            Ok(BackupDescriptor {
-                state: BackupState::Completed,
-                ..
-            }) => {
-                info!("Backup completed");
-                return Ok(());
-            }
-            result => {
-                info!(
-                    "Checking again in 5 seconds, state is {}",
-                    result
-                        .as_ref()
-                        .map(|b| format!("{:?}", b.state))
-                        .unwrap_or(format!("{:?}", result))
-                );
-                sleep(Duration::from_secs(5)).await;
-                continue;
-            }
+        let result = query().await;
+        if backup_completed(component, backup_id, &result)? {
+            info!("Backup completed");
+            return Ok(());
         }
+        if start.elapsed() >= timeout {
+            return Err(Error::Timeout(format!(
+                "{} backup {} did not complete within {}",
+                component,
+                backup_id,
+                format_duration(timeout)
+            )));
+        }
+        info!(
+            "Checking again in {}, state is {}",
+            format_duration(poll_interval),
+            result
+                .as_ref()
+                .map(|b| format!("{:?}", b.state))
+                .unwrap_or_else(|e| e.to_string())
+        );
+        sleep(poll_interval).await;
     }
 }
 
-#[tracing::instrument(skip(kube), err)]
-async fn backup_zeebe_export(kube: &kube::Client, backup_id: u64) -> Result<(), Box<dyn Error>> {
-    let req = SnapshotRequest {
-        indices: "zeebe-record*".into(),
-        feature_states: vec!["none".into()],
-    };
-    let name = format!("camunda_zeebe_records_{}", backup_id);
-    take_snapshot(kube, req, &name).await?;
+/// Whether the backup is completed. States that the backup cannot recover from are errors, while
+/// query errors are retried because the component may be restarting.
+fn backup_completed<T>(
+    component: &str,
+    backup_id: u64,
+    result: &Result<BackupDescriptor<T>, Error>,
+) -> Result<bool, Error> {
+    match result {
+        Ok(backup) => match backup.state {
+            BackupState::Completed => Ok(true),
+            BackupState::InProgress | BackupState::DoesNotExist => Ok(false),
+            BackupState::Failed
+            | BackupState::Incomplete
+            | BackupState::Incompatible
+            | BackupState::Deleted => Err(Error::BackupFailed(format!(
+                "{} backup {} is {:?}",
+                component, backup_id, backup.state
+            ))),
+        },
+        Err(_) => Ok(false),
+    }
+}
 
-    Ok(())
+/// Snapshot metadata is limited to this many bytes of JSON.
+const MAX_METADATA_LENGTH: usize = 1024;
+
+/// Adds the pin, labels and note of the backup to the snapshot metadata. Whatever does not fit is
+/// left out, the catalog always records all of them.
+fn with_create_args(mut metadata: Value, create_args: &CreateArgs) -> Value {
+    let fields = [
+        ("pinned", create_args.pin.then_some(Value::Bool(true))),
+        (
+            "labels",
+            (!create_args.labels.is_empty()).then(|| {
+                Value::Object(
+                    create_args
+                        .labels
+                        .iter()
+                        .map(|(k, v)| (k.clone(), Value::from(v.as_str())))
+                        .collect(),
+                )
+            }),
+        ),
+        ("note", create_args.note.as_deref().map(Value::from)),
+    ];
+    for (key, value) in fields {
+        let Some(value) = value else {
+            continue;
+        };
+        let mut extended = metadata.clone();
+        extended[key] = value;
+        if extended.to_string().len() <= MAX_METADATA_LENGTH {
+            metadata = extended;
+        } else {
+            warn!(
+                "The {} of the backup does not fit into the snapshot metadata",
+                key
+            );
+        }
+    }
+    metadata
 }
 
-#[tracing::instrument(skip(kube), err)]
-async fn backup_zeebe(kube: &kube::Client, backup_id: u64) -> Result<(), Box<dyn Error>> {
-    zeebe::take_backup(kube, backup_id).await?;
-    info!("Started backup");
-    loop {
-        match zeebe::query_backup(kube, backup_id).await {
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_with_create_args() {
+        let create_args = CreateArgs {
+            labels: vec![("reason".to_string(), "pre-upgrade".to_string())],
+            note: Some("before 8.5 upgrade".to_string()),
+            pin: true,
+            ..Default::default()
+        };
+        assert_eq!(
+            with_create_args(json!({}), &create_args),
+            json!({
+                "pinned": true,
+                "labels": {"reason": "pre-upgrade"},
+                "note": "before 8.5 upgrade"
+            })
+        );
+        assert_eq!(
+            with_create_args(json!({}), &CreateArgs::default()),
+            json!({})
+        );
+    }
+
+    #[test]
+    fn test_backup_completed() {
+        let state = |state| -> Result<BackupDescriptor<()>, Error> {
             Ok(BackupDescriptor {
-                state: BackupState::Completed,
-                ..
-            }) => {
-                info!("Backup completed");
-                return Ok(());
-            }
-            result => {
-                info!(
-                    "Checking again in 5 seconds, state is {}",
-                    result
-                        .as_ref()
-                        .map(|b| format!("{:?}", b.state))
-                        .unwrap_or(format!("{:?}", result))
-                );
-                sleep(Duration::from_secs(5)).await;
-                continue;
-            }
+                backup_id: 1,
+                state,
+                details: vec![],
+            })
+        };
+        assert!(backup_completed("Zeebe", 1, &state(BackupState::Completed)).unwrap());
+        assert!(!backup_completed("Zeebe", 1, &state(BackupState::InProgress)).unwrap());
+        assert!(!backup_completed("Zeebe", 1, &state(BackupState::DoesNotExist)).unwrap());
+        assert!(
+            !backup_completed::<()>("Zeebe", 1, &Err(Error::ComponentUnreachable("".into())))
+                .unwrap()
+        );
+        for failed in [
+            BackupState::Failed,
+            BackupState::Incomplete,
+            BackupState::Incompatible,
+            BackupState::Deleted,
+        ] {
+            let e = backup_completed("Operate", 1, &state(failed)).unwrap_err();
+            assert!(matches!(e, Error::BackupFailed(_)), "{:?}", e);
+            assert_eq!(e.exit_code(), 5);
         }
     }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_wait_for_backup_times_out() {
+        let e = wait_for_backup(
+            "Zeebe",
+            1,
+            Duration::from_secs(60),
+            Duration::from_secs(5),
+            || async {
+                Ok(BackupDescriptor::<()> {
+                    backup_id: 1,
+                    state: BackupState::InProgress,
+                    details: vec![],
+                })
+            },
+        )
+        .await
+        .unwrap_err();
+        assert!(matches!(e, Error::Timeout(_)), "{:?}", e);
+        assert_eq!(e.exit_code(), 8);
+    }
+
+    #[test]
+    fn test_next_backup_id() {
+        let now = DateTime::from_timestamp_millis(1_700_000_000_250).unwrap();
+        assert_eq!(
+            next_backup_id(BackupIdStrategy::Seconds, now, None),
+            1_700_000_000
+        );
+        assert_eq!(
+            next_backup_id(BackupIdStrategy::Millis, now, Some(1_700_000_000)),
+            1_700_000_000_250
+        );
+        assert_eq!(
+            next_backup_id(BackupIdStrategy::Monotonic, now, None),
+            1_700_000_000
+        );
+        assert_eq!(
+            next_backup_id(BackupIdStrategy::Monotonic, now, Some(1_699_000_000)),
+            1_700_000_000
+        );
+        // A backup taken within the same second
+        assert_eq!(
+            next_backup_id(BackupIdStrategy::Monotonic, now, Some(1_700_000_000)),
+            1_700_000_001
+        );
+    }
+
+    #[test]
+    fn test_check_backup_id() {
+        let used = BTreeSet::from([1_700_000_000, 1_700_000_000_250]);
+        assert!(check_backup_id(42, &BTreeSet::new()).is_ok());
+        assert!(check_backup_id(1_700_000_001_000, &used).is_ok());
+        // Lower IDs are allowed, e.g. after switching back from the millis strategy
+        assert!(check_backup_id(1_700_000_100, &used).is_ok());
+        let e = check_backup_id(1_700_000_000, &used).unwrap_err();
+        assert_eq!(
+            e.to_string(),
+            "Backup ID 1700000000 is already used, choose another one with --backup-id or \
+             --backup-id-strategy"
+        );
+    }
+
+    #[test]
+    fn test_with_create_args_leaves_out_what_does_not_fit() {
+        let create_args = CreateArgs {
+            labels: vec![("reason".to_string(), "pre-upgrade".to_string())],
+            note: Some("x".repeat(MAX_METADATA_LENGTH)),
+            ..Default::default()
+        };
+        assert_eq!(
+            with_create_args(json!({}), &create_args),
+            json!({"labels": {"reason": "pre-upgrade"}})
+        );
+    }
 }
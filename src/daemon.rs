@@ -0,0 +1,281 @@
+use std::{collections::BTreeSet, num::NonZeroUsize, sync::Arc};
+
+use chrono::Utc;
+use tokio::{task::JoinHandle, time::sleep};
+use tracing::{error, info, warn, Instrument};
+
+use crate::{
+    error::Error,
+    metrics::{self, Metrics},
+    types::{BackupList, BackupState, CreateArgs, DaemonArgs},
+    C8Backup,
+};
+
+/// Creates backups on the schedule until interrupted. A failed backup is logged and does not stop
+/// the daemon, a scheduled time is skipped while the previous backup is still running.
+#[tracing::instrument(skip(client, args), fields(schedule = args.schedule.source()), err)]
+pub(crate) async fn daemon(client: &C8Backup, args: &DaemonArgs) -> Result<(), Error> {
+    if args.create.backup_id.is_some() {
+        return Err("--backup-id cannot be used for scheduled backups".into());
+    }
+    let metrics = Arc::new(Metrics::default());
+    if let Some(address) = args.metrics_address {
+        metrics::serve(address, metrics.clone()).await?;
+        match client.list().await {
+            Ok(list) => metrics.record_list(&list),
+            Err(e) => {
+                warn!(error = %e, "Could not list backups");
+                metrics.record_failure("list");
+            }
+        }
+    }
+    let mut running: Option<JoinHandle<()>> = None;
+    let mut last = Utc::now();
+    loop {
+        let Some(next) = args.schedule.after(&last.max(Utc::now())).next() else {
+            info!("Schedule has no upcoming times, stopping");
+            break;
+        };
+        info!("Next backup at {}", next);
+        tokio::select! {
+            _ = sleep((next - Utc::now()).to_std().unwrap_or_default()) => {}
+            _ = tokio::signal::ctrl_c() => {
+                if let Some(handle) = running.take().filter(|h| !h.is_finished()) {
+                    info!("Waiting for the running backup to stop");
+                    let _ = handle.await;
+                }
+                return Err(Error::Aborted);
+            }
+        }
+        last = next;
+
+        if running.as_ref().is_some_and(|h| !h.is_finished()) {
+            warn!(
+                "Skipping backup scheduled at {}, the previous backup is still running",
+                next
+            );
+            continue;
+        }
+        let span = tracing::info_span!("scheduled_backup", scheduled = %next);
+        running = Some(tokio::spawn(
+            scheduled_backup(
+                client.clone(),
+                args.create.clone(),
+                args.keep,
+                metrics.clone(),
+            )
+            .instrument(span),
+        ));
+    }
+    if let Some(handle) = running {
+        let _ = handle.await;
+    }
+    Ok(())
+}
+
+async fn scheduled_backup(
+    client: C8Backup,
+    create: CreateArgs,
+    keep: Option<NonZeroUsize>,
+    metrics: Arc<Metrics>,
+) {
+    match client.create(&create).await {
+        Ok(backup) => {
+            info!(
+                "Backup {} completed in {:?}",
+                backup.backup_id, backup.duration
+            );
+            metrics.record_created(&backup);
+        }
+        Err(e) => {
+            error!(error = %e, "Backup failed");
+            metrics.record_failure("create");
+            return;
+        }
+    }
+    let list = match client.list().await {
+        Ok(list) => list,
+        Err(e) => {
+            error!(error = %e, "Could not list backups");
+            metrics.record_failure("list");
+            return;
+        }
+    };
+    metrics.record_list(&list);
+    if let Some(keep) = keep {
+        if let Err(e) = client
+            .notified("prune", None, prune(&client, &list, keep))
+            .await
+        {
+            error!(error = %e, "Pruning old backups failed");
+            metrics.record_failure("prune");
+        }
+    }
+}
+
+/// Deletes all backups older than the `keep` most recent usable ones. Backups that fail to delete
+/// are logged and retried by the next prune.
+#[tracing::instrument(skip(client, list), err)]
+async fn prune(
+    client: &C8Backup,
+    list: &BackupList,
+    keep: NonZeroUsize,
+) -> Result<Vec<u64>, Error> {
+    let prunable = prunable_backups(list, keep.get());
+    if prunable.is_empty() {
+        info!("No backups to prune");
+        return Ok(vec![]);
+    }
+    let mut pruned = vec![];
+    let mut failed = 0;
+    for backup_id in &prunable {
+        match client.delete(*backup_id).await {
+            Ok(_) => {
+                info!("Pruned backup {}", backup_id);
+                pruned.push(*backup_id);
+            }
+            // Pinned in the snapshot metadata only
+            Err(Error::Pinned(_)) => info!("Kept pinned backup {}", backup_id),
+            Err(e) => {
+                warn!(error = %e, "Could not prune backup {}", backup_id);
+                failed += 1;
+            }
+        }
+    }
+    if failed > 0 {
+        return Err(format!(
+            "{} of {} backups could not be deleted",
+            failed,
+            prunable.len()
+        )
+        .into());
+    }
+    Ok(pruned)
+}
+
+/// IDs of all backups older than the `keep`-th most recent usable backup, regardless of their
+/// state, except pinned ones. Nothing is pruned while fewer than `keep` usable backups exist, and
+/// newer backups that are not usable yet may still be in progress.
+fn prunable_backups(list: &BackupList, keep: usize) -> Vec<u64> {
+    let usable = usable_backups(list);
+    let Some(&oldest_kept) = usable.iter().rev().nth(keep.saturating_sub(1)) else {
+        return vec![];
+    };
+    let all: BTreeSet<u64> = list
+        .zeebe
+        .iter()
+        .map(|b| b.backup_id)
+        .chain(list.operate.iter().map(|b| b.backup_id))
+        .chain(list.runtime.iter().map(|b| b.backup_id))
+        .collect();
+    let pinned: BTreeSet<u64> = list
+        .catalog
+        .iter()
+        .filter(|e| e.pinned)
+        .map(|e| e.backup_id)
+        .collect();
+    all.range(..oldest_kept)
+        .filter(|id| !pinned.contains(id))
+        .copied()
+        .collect()
+}
+
+fn usable_backups(list: &BackupList) -> BTreeSet<u64> {
+    if list.runtime.is_empty() {
+        let operate: BTreeSet<u64> = list
+            .operate
+            .iter()
+            .filter(|b| b.state == BackupState::Completed)
+            .map(|b| b.backup_id)
+            .collect();
+        list.zeebe
+            .iter()
+            .filter(|b| b.state == BackupState::Completed && operate.contains(&b.backup_id))
+            .map(|b| b.backup_id)
+            .collect()
+    } else {
+        list.runtime
+            .iter()
+            .filter(|b| b.state == BackupState::Completed)
+            .map(|b| b.backup_id)
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::{BackupDescriptor, CatalogEntry, RuntimeBackupInfo};
+
+    fn descriptor<T>(backup_id: u64, state: BackupState) -> BackupDescriptor<T> {
+        BackupDescriptor {
+            backup_id,
+            state,
+            details: vec![],
+        }
+    }
+
+    #[test]
+    fn test_prunable_backups_keeps_most_recent_usable() {
+        let list = BackupList {
+            zeebe: vec![
+                descriptor(1, BackupState::Completed),
+                descriptor(2, BackupState::Failed),
+                descriptor(3, BackupState::Completed),
+                descriptor(4, BackupState::Completed),
+                descriptor(5, BackupState::InProgress),
+            ],
+            operate: vec![
+                descriptor(1, BackupState::Completed),
+                descriptor(3, BackupState::Completed),
+                descriptor(4, BackupState::Completed),
+            ],
+            ..Default::default()
+        };
+        assert_eq!(prunable_backups(&list, 2), vec![1, 2]);
+        assert_eq!(prunable_backups(&list, 3), Vec::<u64>::new());
+        assert_eq!(prunable_backups(&list, 4), Vec::<u64>::new());
+    }
+
+    #[test]
+    fn test_prunable_backups_runtime() {
+        let runtime = |backup_id, state| RuntimeBackupInfo {
+            backup_id,
+            state,
+            failure_reason: None,
+            details: vec![],
+        };
+        let list = BackupList {
+            runtime: vec![
+                runtime(10, BackupState::Completed),
+                runtime(20, BackupState::Incomplete),
+                runtime(30, BackupState::Completed),
+            ],
+            ..Default::default()
+        };
+        assert_eq!(prunable_backups(&list, 1), vec![10, 20]);
+    }
+
+    #[test]
+    fn test_prunable_backups_skips_pinned() {
+        let list = BackupList {
+            zeebe: vec![
+                descriptor(1, BackupState::Completed),
+                descriptor(2, BackupState::Completed),
+                descriptor(3, BackupState::Completed),
+            ],
+            operate: vec![
+                descriptor(1, BackupState::Completed),
+                descriptor(2, BackupState::Completed),
+                descriptor(3, BackupState::Completed),
+            ],
+            catalog: vec![CatalogEntry {
+                backup_id: 1,
+                pinned: true,
+                ..Default::default()
+            }],
+            ..Default::default()
+        };
+        assert_eq!(prunable_backups(&list, 1), vec![2]);
+    }
+}
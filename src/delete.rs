@@ -0,0 +1,163 @@
+use serde_json::Value;
+use tracing::{info, warn};
+
+use crate::{
+    catalog,
+    common::Cluster,
+    elasticsearch::{delete_snapshot, get_snapshot, Elasticsearch, SearchClient},
+    error::Error,
+    opensearch::Opensearch,
+    operate,
+    prefixes::IndexPrefixes,
+    types::{CatalogEntry, DeletedBackup, IndexPrefixArgs, SearchArgs, StorageMode},
+    zeebe,
+};
+
+#[tracing::instrument(skip(cluster, search_args), err)]
+pub(crate) async fn delete(
+    cluster: &Cluster,
+    storage_mode: StorageMode,
+    search_args: &SearchArgs,
+    index_prefixes: &IndexPrefixArgs,
+    catalog: &str,
+    backup_id: u64,
+) -> Result<DeletedBackup, Error> {
+    // Without the catalog, a pinned backup cannot be told apart, so failing to read it fails.
+    let entry = catalog::read(cluster, catalog)
+        .await?
+        .into_iter()
+        .find(|e| e.backup_id == backup_id);
+    if entry.as_ref().is_some_and(|e| e.pinned) {
+        return Err(Error::Pinned(backup_id));
+    }
+    let deleted = match storage_mode {
+        StorageMode::Elasticsearch => {
+            let prefixes = IndexPrefixes::discover(cluster, index_prefixes).await?;
+            let search = Elasticsearch::new(cluster, search_args).await?;
+            delete_es(cluster, &search, &prefixes, entry.as_ref(), backup_id).await?
+        }
+        StorageMode::Opensearch => {
+            let prefixes = IndexPrefixes::discover(cluster, index_prefixes).await?;
+            let search = Opensearch::new(cluster, search_args).await?;
+            delete_es(cluster, &search, &prefixes, entry.as_ref(), backup_id).await?
+        }
+        StorageMode::Rdbms => {
+            ignore_missing(zeebe::delete_runtime_backup(cluster, backup_id).await)?;
+            info!("Deleted runtime backup {}", backup_id);
+            DeletedBackup {
+                backup_id,
+                snapshots: vec![],
+            }
+        }
+    };
+    // The backup is gone, so a stale catalog entry must not fail the deletion.
+    if let Err(e) = catalog::remove(cluster, catalog, backup_id).await {
+        warn!(error = %e, "Could not remove backup {} from catalog {}", backup_id, catalog);
+    }
+    Ok(deleted)
+}
+
+/// Deletes the backups of Operate and Zeebe and the snapshot of exported Zeebe records. Parts
+/// that are already gone are skipped so that a partially deleted backup can be cleaned up.
+#[tracing::instrument(skip(cluster, search, prefixes, entry), err)]
+async fn delete_es(
+    cluster: &Cluster,
+    search: &impl SearchClient,
+    prefixes: &IndexPrefixes,
+    entry: Option<&CatalogEntry>,
+    backup_id: u64,
+) -> Result<DeletedBackup, Error> {
+    let name = entry
+        .and_then(|e| e.zeebe_records_snapshot.clone())
+        .unwrap_or_else(|| prefixes.zeebe_records_snapshot(backup_id));
+    let metadata_pin = match entry {
+        Some(_) => false,
+        None => pinned_in_snapshot(search, &name).await?,
+    };
+    if is_pinned(entry, metadata_pin) {
+        return Err(Error::Pinned(backup_id));
+    }
+    let mut snapshots = match operate::query_backup(cluster, backup_id).await {
+        Ok(backup) => backup
+            .details
+            .into_iter()
+            .map(|d| d.snapshot_name)
+            .collect(),
+        Err(e) if e.is_not_found() => vec![],
+        Err(e) => return Err(e),
+    };
+    ignore_missing(operate::delete_backup(cluster, backup_id).await)?;
+    ignore_missing(zeebe::delete_backup(cluster, backup_id).await)?;
+
+    if delete_snapshot(search, &name).await? {
+        snapshots.push(name);
+    }
+
+    info!("Deleted backup {}", backup_id);
+    Ok(DeletedBackup {
+        backup_id,
+        snapshots,
+    })
+}
+
+/// Whether the backup is pinned. The catalog entry decides when there is one, because `unpin`
+/// cannot change snapshot metadata. Otherwise, a pin recorded there by `create --pin` applies.
+fn is_pinned(entry: Option<&CatalogEntry>, metadata_pin: bool) -> bool {
+    entry.map_or(metadata_pin, |e| e.pinned)
+}
+
+/// Whether `create --pin` recorded a pin in the metadata of the Zeebe records snapshot `name`.
+pub(crate) async fn pinned_in_snapshot(
+    search: &impl SearchClient,
+    name: &str,
+) -> Result<bool, Error> {
+    match get_snapshot(search, name).await {
+        Ok(snapshot) => Ok(pinned_in_metadata(snapshot.metadata.as_ref())),
+        Err(e) if e.is_not_found() => Ok(false),
+        Err(e) => Err(e),
+    }
+}
+
+fn pinned_in_metadata(metadata: Option<&Value>) -> bool {
+    metadata
+        .and_then(|m| m.get("pinned"))
+        .and_then(Value::as_bool)
+        .unwrap_or_default()
+}
+
+fn ignore_missing(result: Result<(), Error>) -> Result<(), Error> {
+    match result {
+        Err(e) if e.is_not_found() => Ok(()),
+        result => result,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn test_pinned_in_metadata() {
+        assert!(pinned_in_metadata(Some(&json!({"pinned": true}))));
+        assert!(!pinned_in_metadata(Some(
+            &json!({"camunda_index_settings": {}})
+        )));
+        assert!(!pinned_in_metadata(None));
+    }
+
+    #[test]
+    fn test_is_pinned() {
+        let entry = |pinned| CatalogEntry {
+            backup_id: 1,
+            pinned,
+            ..Default::default()
+        };
+        assert!(is_pinned(None, true));
+        assert!(!is_pinned(None, false));
+        // An unpinned catalog entry overrides the pin in the snapshot metadata.
+        assert!(!is_pinned(Some(&entry(false)), true));
+        assert!(is_pinned(Some(&entry(true)), false));
+    }
+}
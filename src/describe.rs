@@ -0,0 +1,88 @@
+use tracing::{info, warn};
+
+use crate::{
+    catalog,
+    common::Cluster,
+    error::Error,
+    list::format_labels,
+    operate,
+    types::{BackupDescription, BackupState, StorageMode},
+    zeebe,
+};
+
+/// Collects the state of one backup in every component and its catalog entry.
+#[tracing::instrument(skip(cluster), err)]
+pub(crate) async fn describe(
+    cluster: &Cluster,
+    storage_mode: StorageMode,
+    catalog: &str,
+    backup_id: u64,
+) -> Result<BackupDescription, Error> {
+    let mut description = BackupDescription {
+        backup_id,
+        zeebe: None,
+        operate: None,
+        runtime: None,
+        catalog: None,
+    };
+    match storage_mode {
+        StorageMode::Elasticsearch | StorageMode::Opensearch => {
+            description.zeebe = state(zeebe::query_backup(cluster, backup_id).await, |b| b.state)?;
+            description.operate =
+                state(operate::query_backup(cluster, backup_id).await, |b| b.state)?;
+        }
+        StorageMode::Rdbms => {
+            description.runtime =
+                state(zeebe::query_runtime_backup(cluster, backup_id).await, |b| {
+                    b.state
+                })?;
+        }
+    }
+    description.catalog = match catalog::read(cluster, catalog).await {
+        Ok(entries) => entries.into_iter().find(|e| e.backup_id == backup_id),
+        Err(e) => {
+            warn!("Could not read the backup catalog {}: {}", catalog, e);
+            None
+        }
+    };
+
+    for (component, state) in [
+        ("Zeebe", description.zeebe),
+        ("Operate", description.operate),
+        ("Runtime", description.runtime),
+    ] {
+        if let Some(state) = state {
+            info!("{} backup is {:?}", component, state);
+        }
+    }
+    match &description.catalog {
+        Some(entry) => {
+            info!(
+                "Recorded at {} by {}{}",
+                entry.created_at.as_deref().unwrap_or("unknown time"),
+                entry.operator.as_deref().unwrap_or("unknown"),
+                format_labels(&entry.labels)
+            );
+            if entry.pinned {
+                info!("Pinned, it is neither deleted nor pruned");
+            }
+            if let Some(note) = &entry.note {
+                info!("Note: {}", note);
+            }
+        }
+        None => info!("Backup {} is not recorded in the catalog", backup_id),
+    }
+    Ok(description)
+}
+
+/// State of the backup, `None` if the component does not know it.
+fn state<T>(
+    result: Result<T, Error>,
+    state: impl FnOnce(&T) -> BackupState,
+) -> Result<Option<BackupState>, Error> {
+    match result {
+        Ok(backup) => Ok(Some(state(&backup))),
+        Err(e) if e.is_not_found() => Ok(None),
+        Err(e) => Err(e),
+    }
+}
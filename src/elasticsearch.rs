@@ -1,115 +1,1275 @@
-use std::{collections::HashMap, error::Error};
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap},
+    time::{Duration, Instant},
+};
 
 use bytes::Bytes;
 use http_body_util::Full;
 use hyper::{header::CONTENT_TYPE, Request};
-use tracing::info;
+use percent_encoding::{utf8_percent_encode, AsciiSet, NON_ALPHANUMERIC};
+use tokio::time::sleep;
+use tracing::{info, warn};
 
-use crate::common::make_component_request;
+use crate::{
+    common::{make_component_request, Cluster},
+    error::Error,
+    external::ExternalEndpoint,
+    types::SearchArgs,
+};
+
+/// Repository types that ship with Elasticsearch and OpenSearch.
+pub const STANDARD_REPOSITORY_TYPES: &[&str] = &["fs", "url", "s3", "gcs", "azure", "hdfs"];
 
 #[derive(serde::Serialize, Debug)]
 pub struct SnapshotRequest {
     pub indices: String,
-    pub feature_states: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub feature_states: Option<Vec<String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub metadata: Option<serde_json::Value>,
 }
 
 #[derive(serde::Deserialize, Debug)]
 pub struct SnapshotRepository {
     pub r#type: String,
+    #[serde(default)]
+    pub settings: HashMap<String, serde_json::Value>,
+}
+
+impl SnapshotRepository {
+    /// `url` repositories are always read-only, all others can be registered as read-only.
+    pub fn is_read_only(&self) -> bool {
+        self.r#type == "url"
+            || match self.settings.get("readonly") {
+                Some(serde_json::Value::Bool(readonly)) => *readonly,
+                Some(serde_json::Value::String(readonly)) => readonly == "true",
+                _ => false,
+            }
+    }
+}
+
+/// A search cluster that stores exported Zeebe records and the webapp indices. The snapshot and
+/// index APIs below are shared, implementations only cover what differs between distributions.
+pub trait SearchClient {
+    /// Sends a request to the cluster and returns the response body.
+    async fn request(&self, req: Request<Full<Bytes>>) -> Result<Bytes, Error>;
+
+    /// Builds the body of a snapshot create request for the given index pattern.
+    fn snapshot_request(&self, indices: &str) -> SnapshotRequest;
+
+    /// Whether the given repository is a candidate when no repository was selected explicitly.
+    fn is_usable_repository(&self, name: &str, repository: &SnapshotRepository) -> bool;
+
+    /// The explicitly selected snapshot repository, if any.
+    fn repository(&self) -> Option<&str>;
+
+    /// Interval between two checks of a running snapshot, restore or health wait.
+    fn poll_interval(&self) -> Duration;
+
+    /// Returns the names of all open indices.
+    async fn list_indices(&self) -> Result<Vec<String>, Error> {
+        #[derive(serde::Deserialize, Debug, PartialEq)]
+        struct Index {}
+
+        let req = Request::builder()
+            .uri("/*")
+            .method("GET")
+            .body(Full::default())
+            .expect("Request must be valid");
+        let indices: HashMap<String, Index> = serde_json::from_slice(&self.request(req).await?)?;
+        Ok(indices.keys().cloned().collect())
+    }
+
+    /// Index settings that assign a lifecycle policy to an index. Elasticsearch manages index
+    /// lifecycles with ILM.
+    fn lifecycle_policy_settings(&self) -> &'static [&'static str] {
+        &["index.lifecycle.name"]
+    }
+
+    /// Returns all lifecycle policies with the bodies that recreate them, keyed by name.
+    async fn get_lifecycle_policies(&self) -> Result<BTreeMap<String, serde_json::Value>, Error> {
+        let req = Request::builder()
+            .uri("/_ilm/policy")
+            .method("GET")
+            .body(Full::default())
+            .expect("Request must be valid");
+        let policies: BTreeMap<String, serde_json::Value> =
+            serde_json::from_slice(&self.request(req).await?)?;
+        Ok(policies
+            .into_iter()
+            .map(|(name, policy)| (name, policy["policy"].clone()))
+            .collect())
+    }
+
+    /// Creates a lifecycle policy from a body returned by [`SearchClient::get_lifecycle_policies`].
+    async fn put_lifecycle_policy(
+        &self,
+        name: &str,
+        policy: &serde_json::Value,
+    ) -> Result<(), Error> {
+        let req = Request::builder()
+            .uri(format!("/_ilm/policy/{}", name))
+            .method("PUT")
+            .header(CONTENT_TYPE, "application/json")
+            .body(Full::from(
+                serde_json::json!({ "policy": policy }).to_string(),
+            ))
+            .expect("Request must be valid");
+        self.request(req).await?;
+        Ok(())
+    }
 }
 
-async fn make_elasticsearch_request(
-    kube: &kube::Client,
-    req: Request<Full<Bytes>>,
-) -> Result<Bytes, Box<dyn std::error::Error>> {
-    make_component_request(kube, "app=elasticsearch-master", 9200, req).await
+/// How requests reach the search cluster.
+pub enum Connection {
+    /// Port-forward to a pod matching the label selector.
+    PortForward {
+        cluster: Cluster,
+        selector: String,
+    },
+    External(ExternalEndpoint),
+}
+
+impl Connection {
+    /// Connects to the configured external cluster or falls back to port-forwarding.
+    pub async fn new(cluster: &Cluster, args: &SearchArgs, selector: &str) -> Result<Self, Error> {
+        match &args.search_url {
+            Some(url) => Ok(Connection::External(
+                ExternalEndpoint::new(cluster, args, url).await?,
+            )),
+            None => Ok(Connection::PortForward {
+                cluster: cluster.clone(),
+                selector: selector.to_string(),
+            }),
+        }
+    }
+
+    pub async fn request(&self, req: Request<Full<Bytes>>) -> Result<Bytes, Error> {
+        match self {
+            Connection::PortForward { cluster, selector } => {
+                make_component_request(cluster, selector, cluster.settings.search_port, req).await
+            }
+            Connection::External(endpoint) => endpoint.request(req).await,
+        }
+    }
 }
 
-#[tracing::instrument(skip(kube), err)]
+pub struct Elasticsearch {
+    connection: Connection,
+    repository: Option<String>,
+    poll_interval: Duration,
+}
+
+impl Elasticsearch {
+    pub async fn new(cluster: &Cluster, args: &SearchArgs) -> Result<Self, Error> {
+        Ok(Self {
+            connection: Connection::new(cluster, args, &cluster.settings.elasticsearch_selector)
+                .await?,
+            repository: args.snapshot_repository.clone(),
+            poll_interval: cluster.settings.poll_interval,
+        })
+    }
+}
+
+impl SearchClient for Elasticsearch {
+    async fn request(&self, req: Request<Full<Bytes>>) -> Result<Bytes, Error> {
+        self.connection.request(req).await
+    }
+
+    fn snapshot_request(&self, indices: &str) -> SnapshotRequest {
+        SnapshotRequest {
+            indices: indices.to_string(),
+            feature_states: Some(vec!["none".into()]),
+            metadata: None,
+        }
+    }
+
+    fn is_usable_repository(&self, _name: &str, repository: &SnapshotRepository) -> bool {
+        STANDARD_REPOSITORY_TYPES.contains(&repository.r#type.as_str())
+    }
+
+    fn repository(&self) -> Option<&str> {
+        self.repository.as_deref()
+    }
+
+    fn poll_interval(&self) -> Duration {
+        self.poll_interval
+    }
+}
+
+#[derive(serde::Deserialize, Debug, PartialEq, Eq, Clone, Copy)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum SnapshotState {
+    InProgress,
+    Started,
+    Success,
+    Partial,
+    Failed,
+    Aborted,
+    Incompatible,
+}
+
+#[derive(serde::Deserialize, Debug)]
+pub struct ShardFailure {
+    #[serde(default)]
+    pub index: Option<String>,
+    #[serde(default)]
+    pub shard_id: Option<u32>,
+    #[serde(default)]
+    pub reason: Option<String>,
+}
+
+impl std::fmt::Display for ShardFailure {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}[{}]: {}",
+            self.index.as_deref().unwrap_or("?"),
+            self.shard_id.map_or("?".to_string(), |id| id.to_string()),
+            self.reason.as_deref().unwrap_or("unknown reason")
+        )
+    }
+}
+
+#[derive(serde::Deserialize, Debug, Default)]
+pub struct ShardsSummary {
+    pub total: u32,
+    pub successful: u32,
+    pub failed: u32,
+}
+
+/// Snapshot as described by `GET /_snapshot/<repo>/<name>`.
+#[derive(serde::Deserialize, Debug)]
+pub struct SnapshotInfo {
+    pub state: SnapshotState,
+    #[serde(default)]
+    pub indices: Vec<String>,
+    #[serde(default)]
+    pub failures: Vec<ShardFailure>,
+    #[serde(default)]
+    pub shards: ShardsSummary,
+    #[serde(default)]
+    pub metadata: Option<serde_json::Value>,
+}
+
+#[derive(serde::Deserialize, Debug, Default)]
+pub struct ShardsStats {
+    pub done: u32,
+    pub total: u32,
+}
+
+#[derive(serde::Deserialize, Debug, Default)]
+pub struct FileStats {
+    pub size_in_bytes: u64,
+}
+
+#[derive(serde::Deserialize, Debug, Default)]
+pub struct SnapshotStats {
+    #[serde(default)]
+    pub processed: FileStats,
+    #[serde(default)]
+    pub total: FileStats,
+    #[serde(default)]
+    pub time_in_millis: u64,
+}
+
+/// Progress of a snapshot as described by `GET /_snapshot/<repo>/<name>/_status`.
+#[derive(serde::Deserialize, Debug)]
+pub struct SnapshotStatus {
+    pub state: SnapshotState,
+    #[serde(default)]
+    pub shards_stats: ShardsStats,
+    #[serde(default)]
+    pub stats: SnapshotStats,
+}
+
+/// Starts a snapshot and waits until it is finished, reporting progress along the way.
+/// Fails if the snapshot ends up partial or failed.
+#[tracing::instrument(skip(search, metadata), err)]
 pub async fn take_snapshot(
-    kube: &kube::Client,
-    req: SnapshotRequest,
+    search: &impl SearchClient,
+    indices: &str,
     name: &str,
-) -> Result<(), Box<dyn Error>> {
-    let repo = find_snapshot_repository(kube).await?;
+    metadata: Option<serde_json::Value>,
+) -> Result<(), Error> {
+    let (repo, settings) = find_snapshot_repository(search).await?;
+    if settings.is_read_only() {
+        return Err(format!("Snapshot repository {} is read-only", repo).into());
+    }
+    let snapshot_request = SnapshotRequest {
+        metadata,
+        ..search.snapshot_request(indices)
+    };
     let req = Request::builder()
         .method("POST")
         .uri(format!(
-            "/_snapshot/{}/{}?wait_for_completion=true",
+            "/_snapshot/{}/{}?wait_for_completion=false",
             repo, name
         ))
         .header(CONTENT_TYPE, "application/json")
         .body(Full::from(
-            serde_json::to_string(&req).expect("Snapshot request must be serializable"),
+            serde_json::to_string(&snapshot_request)
+                .expect("Snapshot request must be serializable"),
         ))?;
 
-    make_elasticsearch_request(kube, req).await?;
-    Ok(())
+    search.request(req).await?;
+    info!("Started snapshot {}", name);
+
+    loop {
+        let status = get_snapshot_status(search, &repo, name).await?;
+        info!(
+            "Snapshot {} is {:?}, {}",
+            name,
+            status.state,
+            format_progress(
+                status.shards_stats.done,
+                status.shards_stats.total,
+                status.stats.processed.size_in_bytes,
+                status.stats.total.size_in_bytes,
+                Duration::from_millis(status.stats.time_in_millis),
+            )
+        );
+        if !matches!(
+            status.state,
+            SnapshotState::InProgress | SnapshotState::Started
+        ) {
+            break;
+        }
+        sleep(search.poll_interval()).await;
+    }
+
+    let snapshot = get_snapshot_in(search, &repo, name).await?;
+    match snapshot.state {
+        SnapshotState::Success => {
+            info!("Snapshot {} completed", name);
+            Ok(())
+        }
+        state => {
+            for failure in &snapshot.failures {
+                warn!("Shard {} failed", failure);
+            }
+            Err(Error::BackupFailed(format!(
+                "Snapshot {} is {:?}, {} of {} shards failed: {}",
+                name,
+                state,
+                snapshot.shards.failed,
+                snapshot.shards.total,
+                snapshot
+                    .failures
+                    .iter()
+                    .map(|f| f.to_string())
+                    .collect::<Vec<_>>()
+                    .join("; ")
+            )))
+        }
+    }
 }
 
-#[tracing::instrument(skip(kube), err, level = "debug")]
-pub async fn restore_snapshot(kube: &kube::Client, name: &str) -> Result<(), Box<dyn Error>> {
-    let repo = find_snapshot_repository(kube).await?;
+/// Starts restoring a snapshot and waits until all of its shards are recovered, reporting
+/// progress along the way. `index_settings` are applied to all restored indices. Fails if a
+/// shard cannot be recovered or the shards are not recovered within `timeout`.
+#[tracing::instrument(skip(search), err, level = "debug")]
+pub async fn restore_snapshot(
+    search: &impl SearchClient,
+    name: &str,
+    index_settings: &BTreeMap<String, String>,
+    timeout: Duration,
+) -> Result<(), Error> {
+    let (repo, _) = find_snapshot_repository(search).await?;
+    let snapshot = get_snapshot_in(search, &repo, name).await?;
+    let body = if index_settings.is_empty() {
+        Full::default()
+    } else {
+        Full::from(
+            serde_json::to_string(&serde_json::json!({ "index_settings": index_settings }))
+                .expect("Restore request must be serializable"),
+        )
+    };
     let req = Request::builder()
         .method("POST")
         .uri(format!(
-            "/_snapshot/{}/{}/_restore?wait_for_completion=true",
+            "/_snapshot/{}/{}/_restore?wait_for_completion=false",
             repo, name
         ))
+        .header(CONTENT_TYPE, "application/json")
+        .body(body)?;
+
+    search.request(req).await?;
+    info!("Started restore of snapshot {}", name);
+
+    let start = Instant::now();
+    let expected_shards = snapshot.shards.successful;
+    loop {
+        let recoveries = get_snapshot_recoveries(search, name, &snapshot.indices).await?;
+        let done = recoveries.iter().filter(|r| r.stage == "DONE").count() as u32;
+        let (recovered_bytes, total_bytes) = recoveries.iter().fold((0, 0), |(r, t), shard| {
+            (
+                r + shard.index.size.recovered_in_bytes,
+                t + shard.index.size.total_in_bytes,
+            )
+        });
+        info!(
+            "Restore of {} {}",
+            name,
+            format_progress(
+                done,
+                expected_shards,
+                recovered_bytes,
+                total_bytes,
+                start.elapsed()
+            )
+        );
+        if done >= expected_shards {
+            return Ok(());
+        }
+
+        let failed = get_failed_shards(search, &snapshot.indices).await?;
+        if !failed.is_empty() {
+            for shard in &failed {
+                warn!("Shard {} failed", shard);
+            }
+            return Err(Error::BackupFailed(format!(
+                "Restore of snapshot {} failed, {} shards could not be recovered: {}",
+                name,
+                failed.len(),
+                failed
+                    .iter()
+                    .map(|shard| shard.to_string())
+                    .collect::<Vec<_>>()
+                    .join("; ")
+            )));
+        }
+
+        if start.elapsed() >= timeout {
+            return Err(Error::Timeout(format!(
+                "Restore of snapshot {} did not finish within {}, {} of {} shards recovered",
+                name,
+                humantime::format_duration(timeout),
+                done,
+                expected_shards
+            )));
+        }
+        sleep(search.poll_interval()).await;
+    }
+}
+
+/// Number of allocation attempts of a shard if `index.allocation.max_retries` is not set.
+const DEFAULT_MAX_ALLOCATION_RETRIES: u32 = 5;
+
+/// Routing of the shards of some indices, as returned by `GET /_cluster/state/routing_table`.
+#[derive(serde::Deserialize, Debug, Default)]
+struct RoutingTable {
+    #[serde(default)]
+    indices: HashMap<String, IndexRouting>,
+}
+
+#[derive(serde::Deserialize, Debug)]
+struct IndexRouting {
+    shards: BTreeMap<String, Vec<ShardRouting>>,
+}
+
+#[derive(serde::Deserialize, Debug)]
+struct ShardRouting {
+    state: String,
+    primary: bool,
+    #[serde(default)]
+    unassigned_info: Option<UnassignedInfo>,
+}
+
+#[derive(serde::Deserialize, Debug)]
+struct UnassignedInfo {
+    reason: String,
+    #[serde(default)]
+    failed_attempts: u32,
+    #[serde(default)]
+    details: Option<String>,
+}
+
+/// A primary shard whose allocation failed, for example because its recovery from a snapshot
+/// failed.
+#[derive(Debug, PartialEq, Eq)]
+struct FailedShard {
+    index: String,
+    shard: String,
+    failed_attempts: u32,
+    details: Option<String>,
+}
+
+impl RoutingTable {
+    /// Primary shards whose allocation failed. Replicas are recovered from their primary instead
+    /// of the snapshot, so they are left out.
+    fn failed_primaries(self) -> Vec<FailedShard> {
+        let mut failed = self
+            .indices
+            .into_iter()
+            .flat_map(|(index, routing)| {
+                routing.shards.into_iter().flat_map(move |(shard, copies)| {
+                    let index = index.clone();
+                    copies.into_iter().filter_map(move |copy| {
+                        let info = copy.unassigned_info?;
+                        (copy.primary
+                            && copy.state == "UNASSIGNED"
+                            && info.reason == "ALLOCATION_FAILED")
+                            .then(|| FailedShard {
+                                index: index.clone(),
+                                shard: shard.clone(),
+                                failed_attempts: info.failed_attempts,
+                                details: info.details,
+                            })
+                    })
+                })
+            })
+            .collect::<Vec<_>>();
+        failed.sort_by(|a, b| (&a.index, &a.shard).cmp(&(&b.index, &b.shard)));
+        failed
+    }
+}
+
+impl std::fmt::Display for FailedShard {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}[{}] after {} attempts: {}",
+            self.index,
+            self.shard,
+            self.failed_attempts,
+            self.details.as_deref().unwrap_or("unknown reason")
+        )
+    }
+}
+
+/// Returns the primary shards of the given indices whose allocation failed and that have used up
+/// their `index.allocation.max_retries`, so Elasticsearch no longer tries to recover them. Shards
+/// with retries left are allocated again once recovery slots are free.
+async fn get_failed_shards(
+    search: &impl SearchClient,
+    indices: &[String],
+) -> Result<Vec<FailedShard>, Error> {
+    #[derive(serde::Deserialize, Debug)]
+    struct ClusterState {
+        #[serde(default)]
+        routing_table: RoutingTable,
+    }
+
+    let mut failed = Vec::new();
+    for batch in name_batches(indices, MAX_NAMES_LENGTH) {
+        let req = Request::builder()
+            .method("GET")
+            .uri(format!("/_cluster/state/routing_table/{}", batch))
+            .body(Full::default())?;
+        let state: ClusterState = serde_json::from_slice(&search.request(req).await?)?;
+        failed.extend(state.routing_table.failed_primaries());
+    }
+    if failed.is_empty() {
+        return Ok(failed);
+    }
+
+    let failed_indices = failed
+        .iter()
+        .map(|shard| shard.index.clone())
+        .collect::<BTreeSet<_>>()
+        .into_iter()
+        .collect::<Vec<_>>();
+    let max_retries = get_max_allocation_retries(search, &failed_indices).await?;
+    Ok(exhausted_retries(failed, &max_retries))
+}
+
+/// The failed shards that were attempted at least as often as their index allows.
+fn exhausted_retries(
+    failed: Vec<FailedShard>,
+    max_retries: &HashMap<String, u32>,
+) -> Vec<FailedShard> {
+    failed
+        .into_iter()
+        .filter(|shard| {
+            let max_retries = max_retries
+                .get(&shard.index)
+                .copied()
+                .unwrap_or(DEFAULT_MAX_ALLOCATION_RETRIES);
+            shard.failed_attempts >= max_retries
+        })
+        .collect()
+}
+
+/// Returns `index.allocation.max_retries` of the given indices, including the default.
+async fn get_max_allocation_retries(
+    search: &impl SearchClient,
+    indices: &[String],
+) -> Result<HashMap<String, u32>, Error> {
+    #[derive(serde::Deserialize, Debug)]
+    struct IndexSettings {
+        #[serde(default)]
+        settings: HashMap<String, serde_json::Value>,
+        #[serde(default)]
+        defaults: HashMap<String, serde_json::Value>,
+    }
+
+    let mut max_retries = HashMap::new();
+    for batch in name_batches(indices, MAX_NAMES_LENGTH) {
+        let req = Request::builder()
+            .method("GET")
+            .uri(format!(
+                "/{}/_settings/index.allocation.max_retries?include_defaults=true&flat_settings=true",
+                batch
+            ))
+            .body(Full::default())?;
+        let resp: HashMap<String, IndexSettings> =
+            serde_json::from_slice(&search.request(req).await?)?;
+        max_retries.extend(resp.into_iter().filter_map(|(index, settings)| {
+            let value = settings
+                .settings
+                .get("index.allocation.max_retries")
+                .or(settings.defaults.get("index.allocation.max_retries"))?
+                .as_str()?
+                .parse()
+                .ok()?;
+            Some((index, value))
+        }));
+    }
+    Ok(max_retries)
+}
+
+#[tracing::instrument(skip(search), err, level = "debug")]
+async fn get_snapshot_status(
+    search: &impl SearchClient,
+    repo: &str,
+    name: &str,
+) -> Result<SnapshotStatus, Error> {
+    #[derive(serde::Deserialize, Debug)]
+    struct Statuses {
+        snapshots: Vec<SnapshotStatus>,
+    }
+
+    let req = Request::builder()
+        .method("GET")
+        .uri(format!("/_snapshot/{}/{}/_status", repo, name))
+        .body(Full::default())?;
+    let resp: Statuses = serde_json::from_slice(&search.request(req).await?)?;
+    resp.snapshots
+        .into_iter()
+        .next()
+        .ok_or_else(|| format!("Snapshot {} has no status", name).into())
+}
+
+#[derive(serde::Deserialize, Debug)]
+struct RecoverySize {
+    total_in_bytes: u64,
+    recovered_in_bytes: u64,
+}
+
+#[derive(serde::Deserialize, Debug)]
+struct RecoveryIndex {
+    size: RecoverySize,
+}
+
+#[derive(serde::Deserialize, Debug)]
+struct RecoverySource {
+    #[serde(default)]
+    snapshot: Option<String>,
+}
+
+#[derive(serde::Deserialize, Debug)]
+struct ShardRecovery {
+    r#type: String,
+    stage: String,
+    source: RecoverySource,
+    index: RecoveryIndex,
+}
+
+/// Returns the shard recoveries of the given indices that restore from the snapshot.
+async fn get_snapshot_recoveries(
+    search: &impl SearchClient,
+    name: &str,
+    indices: &[String],
+) -> Result<Vec<ShardRecovery>, Error> {
+    #[derive(serde::Deserialize, Debug)]
+    struct IndexRecovery {
+        shards: Vec<ShardRecovery>,
+    }
+
+    let mut recoveries = Vec::new();
+    for batch in name_batches(indices, MAX_NAMES_LENGTH) {
+        let req = Request::builder()
+            .method("GET")
+            .uri(format!(
+                "/{}/_recovery?active_only=false&ignore_unavailable=true",
+                batch
+            ))
+            .body(Full::default())?;
+        let resp: HashMap<String, IndexRecovery> =
+            serde_json::from_slice(&search.request(req).await?)?;
+        recoveries.extend(
+            resp.into_values()
+                .flat_map(|index| index.shards)
+                .filter(|shard| {
+                    shard.r#type == "SNAPSHOT" && shard.source.snapshot.as_deref() == Some(name)
+                }),
+        );
+    }
+    Ok(recoveries)
+}
+
+/// Describes progress as shards and bytes done together with the average throughput.
+fn format_progress(
+    done_shards: u32,
+    total_shards: u32,
+    done_bytes: u64,
+    total_bytes: u64,
+    elapsed: Duration,
+) -> String {
+    let throughput = if elapsed.is_zero() {
+        0
+    } else {
+        (done_bytes as f64 / elapsed.as_secs_f64()) as u64
+    };
+    format!(
+        "{}/{} shards, {} of {} ({}/s)",
+        done_shards,
+        total_shards,
+        format_bytes(done_bytes),
+        format_bytes(total_bytes),
+        format_bytes(throughput)
+    )
+}
+
+fn format_bytes(bytes: u64) -> String {
+    const UNITS: &[&str] = &["B", "KiB", "MiB", "GiB", "TiB"];
+    let mut value = bytes as f64;
+    let mut unit = 0;
+    while value >= 1024.0 && unit < UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{} {}", bytes, UNITS[0])
+    } else {
+        format!("{:.1} {}", value, UNITS[unit])
+    }
+}
+
+/// Health of indices, ordered from best to worst.
+#[derive(serde::Deserialize, Debug, PartialEq, Eq, PartialOrd, Ord, Clone, Copy)]
+#[serde(rename_all = "lowercase")]
+pub enum HealthStatus {
+    Green,
+    Yellow,
+    Red,
+}
+
+/// Waits until the given indices are green, or at least yellow if `allow_yellow` is set. Fails
+/// with [`Error::Timeout`] if they are not healthy within `timeout`. The health is queried in
+/// batches of indices that fit in a request line, all batches must be healthy.
+#[tracing::instrument(skip(search, indices), err)]
+pub async fn wait_for_health(
+    search: &impl SearchClient,
+    indices: &[String],
+    allow_yellow: bool,
+    timeout: Duration,
+) -> Result<(), Error> {
+    #[derive(serde::Deserialize, Debug)]
+    struct Health {
+        status: HealthStatus,
+        #[serde(default)]
+        initializing_shards: u32,
+        #[serde(default)]
+        unassigned_shards: u32,
+    }
+
+    if indices.is_empty() {
+        return Ok(());
+    }
+    let start = Instant::now();
+    loop {
+        let mut health = Health {
+            status: HealthStatus::Green,
+            initializing_shards: 0,
+            unassigned_shards: 0,
+        };
+        for batch in name_batches(indices, MAX_NAMES_LENGTH) {
+            let req = Request::builder()
+                .method("GET")
+                .uri(format!("/_cluster/health/{}", batch))
+                .body(Full::default())?;
+            let batch_health: Health = serde_json::from_slice(&search.request(req).await?)?;
+            health.status = health.status.max(batch_health.status);
+            health.initializing_shards += batch_health.initializing_shards;
+            health.unassigned_shards += batch_health.unassigned_shards;
+        }
+        if health.status == HealthStatus::Green
+            || (allow_yellow && health.status == HealthStatus::Yellow)
+        {
+            info!("Restored indices are {:?}", health.status);
+            return Ok(());
+        }
+        if start.elapsed() >= timeout {
+            return Err(Error::Timeout(format!(
+                "Restored indices are still {:?} after {}, {} shards are initializing and {} unassigned",
+                health.status,
+                humantime::format_duration(timeout),
+                health.initializing_shards,
+                health.unassigned_shards
+            )));
+        }
+        info!(
+            "Checking again in {}, health is {:?} with {} initializing and {} unassigned shards",
+            humantime::format_duration(search.poll_interval()),
+            health.status,
+            health.initializing_shards,
+            health.unassigned_shards
+        );
+        sleep(search.poll_interval()).await;
+    }
+}
+
+#[tracing::instrument(skip(search), err)]
+pub async fn get_all_indices(search: &impl SearchClient) -> Result<Vec<String>, Error> {
+    search.list_indices().await
+}
+
+/// Returns the names of all indices contained in the given snapshot.
+#[tracing::instrument(skip(search), err, level = "debug")]
+pub async fn get_snapshot_indices(
+    search: &impl SearchClient,
+    name: &str,
+) -> Result<Vec<String>, Error> {
+    Ok(get_snapshot(search, name).await?.indices)
+}
+
+#[tracing::instrument(skip(search), err, level = "debug")]
+pub async fn get_snapshot(search: &impl SearchClient, name: &str) -> Result<SnapshotInfo, Error> {
+    let (repo, _) = find_snapshot_repository(search).await?;
+    get_snapshot_in(search, &repo, name).await
+}
+
+/// Deletes a snapshot, returns whether it existed.
+#[tracing::instrument(skip(search), err)]
+pub async fn delete_snapshot(search: &impl SearchClient, name: &str) -> Result<bool, Error> {
+    let (repo, _) = find_snapshot_repository(search).await?;
+    let req = Request::builder()
+        .method("DELETE")
+        .uri(format!("/_snapshot/{}/{}", repo, name))
+        .body(Full::default())?;
+    match search.request(req).await {
+        Ok(_) => {
+            info!("Deleted snapshot {}", name);
+            Ok(true)
+        }
+        Err(e) if e.is_not_found() => Ok(false),
+        Err(e) => Err(e),
+    }
+}
+
+/// Returns the given settings of all indices, including defaults, keyed by index name.
+#[tracing::instrument(skip(search), err, level = "debug")]
+pub async fn get_index_settings(
+    search: &impl SearchClient,
+    names: &[&str],
+) -> Result<HashMap<String, HashMap<String, String>>, Error> {
+    #[derive(serde::Deserialize, Debug)]
+    struct IndexSettings {
+        #[serde(default)]
+        settings: HashMap<String, serde_json::Value>,
+        #[serde(default)]
+        defaults: HashMap<String, serde_json::Value>,
+    }
+
+    let req = Request::builder()
+        .method("GET")
+        .uri(format!(
+            "/_settings/{}?include_defaults=true&flat_settings=true",
+            names.join(",")
+        ))
         .body(Full::default())?;
+    let resp: HashMap<String, IndexSettings> = serde_json::from_slice(&search.request(req).await?)?;
+    Ok(resp
+        .into_iter()
+        .map(|(index, settings)| {
+            let values = settings
+                .defaults
+                .into_iter()
+                .chain(settings.settings)
+                .filter_map(|(name, value)| match value {
+                    serde_json::Value::String(value) => Some((name, value)),
+                    _ => None,
+                })
+                .collect();
+            (index, values)
+        })
+        .collect())
+}
 
-    make_elasticsearch_request(kube, req).await?;
+/// Updates the settings of the given indices, `null` values reset a setting to its default.
+#[tracing::instrument(skip(search, indices), err, level = "debug")]
+pub async fn put_index_settings(
+    search: &impl SearchClient,
+    indices: &[String],
+    settings: &serde_json::Map<String, serde_json::Value>,
+) -> Result<(), Error> {
+    for batch in name_batches(indices, MAX_NAMES_LENGTH) {
+        let req = Request::builder()
+            .method("PUT")
+            .uri(format!("/{}/_settings?expand_wildcards=none", batch))
+            .header(CONTENT_TYPE, "application/json")
+            .body(Full::from(
+                serde_json::to_string(settings).expect("Settings must be serializable"),
+            ))?;
+        search.request(req).await?;
+    }
     Ok(())
 }
 
-#[tracing::instrument(skip(kube), err)]
-pub async fn get_all_indices(kube: &kube::Client) -> Result<Vec<String>, Box<dyn Error>> {
-    #[derive(serde::Deserialize, Debug, PartialEq)]
-    struct Index {}
+async fn get_snapshot_in(
+    search: &impl SearchClient,
+    repo: &str,
+    name: &str,
+) -> Result<SnapshotInfo, Error> {
+    #[derive(serde::Deserialize, Debug)]
+    struct Snapshots {
+        snapshots: Vec<SnapshotInfo>,
+    }
 
     let req = Request::builder()
-        .uri("/*")
         .method("GET")
-        .body(Full::default())
-        .expect("Request must be valid");
-    let indices: std::collections::HashMap<String, Index> =
-        serde_json::from_slice(&make_elasticsearch_request(kube, req).await?)?;
-    Ok(indices.keys().cloned().collect())
+        .uri(format!("/_snapshot/{}/{}", repo, name))
+        .body(Full::default())?;
+
+    let resp: Snapshots = serde_json::from_slice(&search.request(req).await?)?;
+    resp.snapshots
+        .into_iter()
+        .next()
+        .ok_or_else(|| format!("Snapshot {} does not exist", name).into())
+}
+
+/// Characters that are percent-encoded in index and data stream names.
+const NAME_ENCODE_SET: &AsciiSet = &NON_ALPHANUMERIC.remove(b'-').remove(b'_').remove(b'.');
+
+/// Upper bound for the comma-separated names in a single request path, well below the default
+/// 4kb limit of the HTTP request line.
+const MAX_NAMES_LENGTH: usize = 3000;
+
+/// Percent-encodes the names and joins them into comma-separated batches that fit in a path.
+fn name_batches(names: &[String], max_length: usize) -> Vec<String> {
+    let mut batches = Vec::new();
+    let mut batch = String::new();
+    for name in names {
+        let encoded = utf8_percent_encode(name, NAME_ENCODE_SET).to_string();
+        if !batch.is_empty() && batch.len() + 1 + encoded.len() > max_length {
+            batches.push(std::mem::take(&mut batch));
+        }
+        if !batch.is_empty() {
+            batch.push(',');
+        }
+        batch.push_str(&encoded);
+    }
+    if !batch.is_empty() {
+        batches.push(batch);
+    }
+    batches
+}
+
+/// Deletes the given concrete indices with as few multi-index requests as possible.
+#[tracing::instrument(skip(search, names), err, level = "debug")]
+pub async fn bulk_delete_indices(
+    search: &impl SearchClient,
+    names: &[String],
+) -> Result<(), Error> {
+    for batch in name_batches(names, MAX_NAMES_LENGTH) {
+        let req = Request::builder()
+            .uri(format!(
+                "/{}?ignore_unavailable=true&expand_wildcards=none",
+                batch
+            ))
+            .method("DELETE")
+            .body(Full::default())?;
+        search.request(req).await?;
+    }
+    for name in names {
+        info!("Deleted index {}", name);
+    }
+    Ok(())
+}
+
+#[derive(serde::Deserialize, Debug, Clone)]
+pub struct DataStream {
+    pub name: String,
+    pub indices: Vec<BackingIndex>,
 }
 
-#[tracing::instrument(skip(kube), err, level = "debug")]
-pub async fn delete_index(kube: &kube::Client, name: &str) -> Result<(), Box<dyn Error>> {
+#[derive(serde::Deserialize, Debug, Clone)]
+pub struct BackingIndex {
+    pub index_name: String,
+}
+
+#[tracing::instrument(skip(search), err, level = "debug")]
+pub async fn get_data_streams(search: &impl SearchClient) -> Result<Vec<DataStream>, Error> {
+    #[derive(serde::Deserialize, Debug)]
+    struct DataStreams {
+        data_streams: Vec<DataStream>,
+    }
+
     let req = Request::builder()
-        .uri(format!("/{name}"))
-        .method("DELETE")
-        .body(Full::default())
-        .expect("Request must be valid");
+        .method("GET")
+        .uri("/_data_stream")
+        .body(Full::default())?;
+    let resp: DataStreams = serde_json::from_slice(&search.request(req).await?)?;
+    Ok(resp.data_streams)
+}
 
-    make_elasticsearch_request(kube, req).await?;
-    info!("Deleted index {}", name);
+/// Deletes the given data streams together with their backing indices.
+#[tracing::instrument(skip(search, names), err, level = "debug")]
+pub async fn delete_data_streams(
+    search: &impl SearchClient,
+    names: &[String],
+) -> Result<(), Error> {
+    for batch in name_batches(names, MAX_NAMES_LENGTH) {
+        let req = Request::builder()
+            .uri(format!("/_data_stream/{}?expand_wildcards=none", batch))
+            .method("DELETE")
+            .body(Full::default())?;
+        search.request(req).await?;
+    }
+    for name in names {
+        info!("Deleted data stream {}", name);
+    }
     Ok(())
 }
 
-#[tracing::instrument(skip(kube), err, level = "debug")]
-async fn find_snapshot_repository(kube: &kube::Client) -> Result<String, Box<dyn Error>> {
+#[derive(serde::Deserialize, Debug, Clone)]
+pub struct Alias {
+    pub alias: String,
+    pub index: String,
+}
+
+#[tracing::instrument(skip(search), err, level = "debug")]
+pub async fn get_aliases(search: &impl SearchClient) -> Result<Vec<Alias>, Error> {
+    let req = Request::builder()
+        .method("GET")
+        .uri("/_cat/aliases?format=json&h=alias,index")
+        .body(Full::default())?;
+    Ok(serde_json::from_slice(&search.request(req).await?)?)
+}
+
+#[tracing::instrument(skip(search), err, level = "debug")]
+async fn find_snapshot_repository(
+    search: &impl SearchClient,
+) -> Result<(String, SnapshotRepository), Error> {
     let req = Request::builder()
         .method("GET")
         .uri("/_snapshot/_all")
         .body(Full::default())?;
 
-    let resp = make_elasticsearch_request(kube, req).await?;
+    let resp = search.request(req).await?;
 
-    let repoitories = serde_json::from_slice::<HashMap<String, SnapshotRepository>>(&resp)?;
-    for (name, settings) in repoitories {
+    let mut repositories = serde_json::from_slice::<BTreeMap<String, SnapshotRepository>>(&resp)?;
+    for (name, settings) in &repositories {
         tracing::debug!(
             "Found snapshot repository {} with settings {:?}",
             name,
             settings
         );
-        if settings.r#type == "gcs" || settings.r#type == "s3" {
-            tracing::debug!("Using repository {}", name);
-            return Ok(name);
-        }
     }
-    Err("No snapshot repository found".into())
+    let name = select_snapshot_repository(&repositories, search.repository(), |name, repo| {
+        search.is_usable_repository(name, repo)
+    })?;
+    tracing::debug!("Using repository {}", name);
+    let settings = repositories
+        .remove(&name)
+        .expect("Selected repository must exist");
+    Ok((name, settings))
+}
+
+/// Picks the explicitly requested repository or the only usable one.
+fn select_snapshot_repository(
+    repositories: &BTreeMap<String, SnapshotRepository>,
+    requested: Option<&str>,
+    is_usable: impl Fn(&str, &SnapshotRepository) -> bool,
+) -> Result<String, Error> {
+    if let Some(requested) = requested {
+        return match repositories.get(requested) {
+            Some(_) => Ok(requested.to_string()),
+            None => Err(format!("Snapshot repository {} does not exist", requested).into()),
+        };
+    }
+
+    let candidates = repositories
+        .iter()
+        .filter(|(name, repo)| is_usable(name, repo))
+        .map(|(name, _)| name.clone())
+        .collect::<Vec<_>>();
+    match candidates.as_slice() {
+        [] => Err("No snapshot repository found".into()),
+        [name] => Ok(name.clone()),
+        names => Err(format!(
+            "Found multiple snapshot repositories ({}), select one with --snapshot-repository",
+            names.join(", ")
+        )
+        .into()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn repositories() -> BTreeMap<String, SnapshotRepository> {
+        serde_json::from_str(
+            r#"{
+                "backups": {"type": "s3", "settings": {"bucket": "camunda"}},
+                "archive": {"type": "url", "settings": {"url": "https://example.com"}},
+                "local": {"type": "fs", "settings": {"location": "/backups", "readonly": "true"}}
+            }"#,
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn test_select_requested_repository() {
+        let name = select_snapshot_repository(&repositories(), Some("archive"), |_, _| true);
+        assert_eq!(name.unwrap(), "archive");
+    }
+
+    #[test]
+    fn test_select_missing_repository_is_error() {
+        let name = select_snapshot_repository(&repositories(), Some("missing"), |_, _| true);
+        assert!(name.is_err());
+    }
+
+    #[test]
+    fn test_select_single_usable_repository() {
+        let name = select_snapshot_repository(&repositories(), None, |_, repo| repo.r#type == "s3");
+        assert_eq!(name.unwrap(), "backups");
+    }
+
+    #[test]
+    fn test_select_ambiguous_repository_is_error() {
+        let err = select_snapshot_repository(&repositories(), None, |_, _| true).unwrap_err();
+        assert!(err.to_string().contains("archive, backups, local"));
+    }
+
+    #[test]
+    fn test_name_batches() {
+        let names = vec![
+            "operate-list-view-8.1.0_".to_string(),
+            "zeebe-record_job_8.2.3_2023-05-04".to_string(),
+            "odd+name".to_string(),
+        ];
+        assert_eq!(
+            name_batches(&names, usize::MAX),
+            vec!["operate-list-view-8.1.0_,zeebe-record_job_8.2.3_2023-05-04,odd%2Bname"]
+        );
+        assert_eq!(
+            name_batches(&names, 40),
+            vec![
+                "operate-list-view-8.1.0_",
+                "zeebe-record_job_8.2.3_2023-05-04",
+                "odd%2Bname"
+            ]
+        );
+        assert!(name_batches(&[], 40).is_empty());
+    }
+
+    #[test]
+    fn test_worst_health_status() {
+        let statuses = [HealthStatus::Green, HealthStatus::Red, HealthStatus::Yellow];
+        assert_eq!(statuses.iter().max(), Some(&HealthStatus::Red));
+        assert_eq!(
+            HealthStatus::Green.max(HealthStatus::Yellow),
+            HealthStatus::Yellow
+        );
+    }
+
+    #[test]
+    fn test_format_progress() {
+        assert_eq!(
+            format_progress(3, 5, 512, 3 * 1024 * 1024, Duration::from_secs(2)),
+            "3/5 shards, 512 B of 3.0 MiB (256 B/s)"
+        );
+        assert_eq!(
+            format_progress(0, 0, 0, 0, Duration::ZERO),
+            "0/0 shards, 0 B of 0 B (0 B/s)"
+        );
+    }
+
+    #[test]
+    fn test_partial_snapshot_info_deserialize() {
+        let info: SnapshotInfo = serde_json::from_str(
+            r#"{
+                "snapshot": "camunda_zeebe_records_1",
+                "uuid": "abc",
+                "state": "PARTIAL",
+                "indices": ["zeebe-record_job_8.2.3_2023-05-04"],
+                "failures": [{
+                    "index": "zeebe-record_job_8.2.3_2023-05-04",
+                    "index_uuid": "def",
+                    "shard_id": 0,
+                    "reason": "IndexShardSnapshotFailedException[disk full]",
+                    "node_id": "node-1",
+                    "status": "INTERNAL_SERVER_ERROR"
+                }],
+                "shards": {"total": 2, "failed": 1, "successful": 1}
+            }"#,
+        )
+        .unwrap();
+        assert_eq!(info.state, SnapshotState::Partial);
+        assert_eq!(info.shards.failed, 1);
+        assert_eq!(
+            info.failures[0].to_string(),
+            "zeebe-record_job_8.2.3_2023-05-04[0]: IndexShardSnapshotFailedException[disk full]"
+        );
+    }
+
+    #[test]
+    fn test_failed_primaries() {
+        let routing: RoutingTable = serde_json::from_str(
+            r#"{"indices": {"operate-list-view-8.1.0_": {"shards": {
+                "0": [
+                    {"state": "STARTED", "primary": true},
+                    {"state": "UNASSIGNED", "primary": false,
+                     "unassigned_info": {"reason": "ALLOCATION_FAILED", "failed_attempts": 5}}
+                ],
+                "1": [{"state": "UNASSIGNED", "primary": true,
+                       "unassigned_info": {"reason": "NEW_INDEX_RESTORED"}}],
+                "2": [{"state": "UNASSIGNED", "primary": true,
+                       "unassigned_info": {"reason": "ALLOCATION_FAILED", "failed_attempts": 5,
+                        "details": "failed recovery, failure RecoveryFailedException[corrupted]"}}],
+                "3": [{"state": "UNASSIGNED", "primary": true,
+                       "unassigned_info": {"reason": "ALLOCATION_FAILED", "failed_attempts": 1}}]
+            }}}}"#,
+        )
+        .unwrap();
+        let failed = routing.failed_primaries();
+        assert_eq!(
+            failed.iter().map(|s| s.shard.as_str()).collect::<Vec<_>>(),
+            vec!["2", "3"]
+        );
+
+        let failed = exhausted_retries(failed, &HashMap::new());
+        assert_eq!(failed.len(), 1);
+        assert_eq!(
+            failed[0].to_string(),
+            "operate-list-view-8.1.0_[2] after 5 attempts: failed recovery, failure RecoveryFailedException[corrupted]"
+        );
+    }
+
+    #[test]
+    fn test_exhausted_retries_uses_index_setting() {
+        let shard = || FailedShard {
+            index: "zeebe-record_job_8.2.3_2023-05-04".to_string(),
+            shard: "0".to_string(),
+            failed_attempts: 5,
+            details: None,
+        };
+        let max_retries = HashMap::from([("zeebe-record_job_8.2.3_2023-05-04".to_string(), 10)]);
+        assert!(exhausted_retries(vec![shard()], &max_retries).is_empty());
+        let max_retries = HashMap::from([("zeebe-record_job_8.2.3_2023-05-04".to_string(), 3)]);
+        assert_eq!(exhausted_retries(vec![shard()], &max_retries).len(), 1);
+    }
+
+    #[test]
+    fn test_repository_is_read_only() {
+        let repositories = repositories();
+        assert!(!repositories["backups"].is_read_only());
+        assert!(repositories["archive"].is_read_only());
+        assert!(repositories["local"].is_read_only());
+    }
 }
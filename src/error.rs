@@ -0,0 +1,195 @@
+use std::fmt;
+
+use hyper::StatusCode;
+
+/// Errors of backup and restore operations. Each kind maps to a distinct process exit code so that
+/// wrappers can react differently, see [`Error::exit_code`].
+#[derive(Debug)]
+pub enum Error {
+    /// No ready pod of a component was found or the connection to it failed.
+    ComponentUnreachable(String),
+    /// A component or the secondary storage responded with a non-success status.
+    Http { status: StatusCode, body: String },
+    /// A backup or snapshot failed or is incomplete.
+    BackupFailed(String),
+    /// No backup is completed by all components.
+    NoUsableBackup,
+    /// The backup is pinned and must not be deleted.
+    Pinned(u64),
+    /// A request to the Kubernetes API failed.
+    Kubernetes(kube::Error),
+    /// An operation did not finish in time.
+    Timeout(String),
+    /// The user interrupted the operation.
+    Aborted,
+    /// Any other failure, such as invalid arguments or unexpected responses.
+    Other(Box<dyn std::error::Error + Send + Sync>),
+}
+
+impl Error {
+    /// Process exit code of this kind of error. 2 is left to invalid command line usage.
+    pub fn exit_code(&self) -> u8 {
+        match self {
+            Error::Other(_) => 1,
+            Error::ComponentUnreachable(_) => 3,
+            Error::Http { .. } => 4,
+            Error::BackupFailed(_) => 5,
+            Error::NoUsableBackup => 6,
+            Error::Kubernetes(_) => 7,
+            Error::Timeout(_) => 8,
+            Error::Pinned(_) => 9,
+            Error::Aborted => 130,
+        }
+    }
+
+    /// Whether the requested resource does not exist.
+    pub fn is_not_found(&self) -> bool {
+        matches!(
+            self,
+            Error::Http {
+                status: StatusCode::NOT_FOUND,
+                ..
+            }
+        )
+    }
+
+    /// Whether retrying the failed request may succeed.
+    pub fn is_transient(&self) -> bool {
+        match self {
+            Error::ComponentUnreachable(_) | Error::Kubernetes(_) => true,
+            Error::Http { status, .. } => matches!(
+                *status,
+                StatusCode::TOO_MANY_REQUESTS
+                    | StatusCode::BAD_GATEWAY
+                    | StatusCode::SERVICE_UNAVAILABLE
+                    | StatusCode::GATEWAY_TIMEOUT
+            ),
+            _ => false,
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::ComponentUnreachable(reason) => write!(f, "Component unreachable: {}", reason),
+            Error::Http { status, body } => {
+                write!(f, "Request failed with status {}: {}", status, body)
+            }
+            Error::BackupFailed(reason) => write!(f, "Backup failed: {}", reason),
+            Error::NoUsableBackup => write!(f, "No usable backup found"),
+            Error::Pinned(backup_id) => {
+                write!(f, "Backup {} is pinned, unpin it first", backup_id)
+            }
+            Error::Kubernetes(e) => write!(f, "Kubernetes API error: {}", e),
+            Error::Timeout(reason) => write!(f, "Timed out: {}", reason),
+            Error::Aborted => write!(f, "Aborted by user"),
+            Error::Other(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Kubernetes(e) => Some(e),
+            Error::Other(e) => Some(e.as_ref()),
+            _ => None,
+        }
+    }
+}
+
+impl From<kube::Error> for Error {
+    fn from(e: kube::Error) -> Self {
+        Error::Kubernetes(e)
+    }
+}
+
+impl From<kube::runtime::wait::Error> for Error {
+    fn from(e: kube::runtime::wait::Error) -> Self {
+        Error::Other(e.into())
+    }
+}
+
+impl From<hyper::Error> for Error {
+    fn from(e: hyper::Error) -> Self {
+        Error::ComponentUnreachable(e.to_string())
+    }
+}
+
+impl From<std::io::Error> for Error {
+    fn from(e: std::io::Error) -> Self {
+        Error::ComponentUnreachable(e.to_string())
+    }
+}
+
+impl From<tokio::time::error::Elapsed> for Error {
+    fn from(e: tokio::time::error::Elapsed) -> Self {
+        Error::Timeout(e.to_string())
+    }
+}
+
+macro_rules! other_errors {
+    ($($error:ty),* $(,)?) => {
+        $(
+            impl From<$error> for Error {
+                fn from(e: $error) -> Self {
+                    Error::Other(e.into())
+                }
+            }
+        )*
+    };
+}
+
+other_errors!(
+    String,
+    &str,
+    serde_json::Error,
+    hyper::http::Error,
+    hyper::header::InvalidHeaderValue,
+    hyper::http::uri::InvalidUri,
+    rustls::Error,
+    rustls_pki_types::pem::Error,
+    rustls_pki_types::InvalidDnsNameError,
+    toml::de::Error,
+);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_exit_codes_are_distinct() {
+        let errors = [
+            Error::Other("unexpected".into()),
+            Error::ComponentUnreachable("connection refused".into()),
+            Error::Http {
+                status: StatusCode::NOT_FOUND,
+                body: String::new(),
+            },
+            Error::BackupFailed("snapshot failed".into()),
+            Error::NoUsableBackup,
+            Error::Pinned(1683214620),
+            Error::Timeout("deadline has elapsed".into()),
+            Error::Aborted,
+        ];
+        let mut codes = errors.iter().map(Error::exit_code).collect::<Vec<_>>();
+        codes.push(Error::Kubernetes(kube::Error::LinesCodecMaxLineLengthExceeded).exit_code());
+        codes.sort();
+        codes.dedup();
+        assert_eq!(codes.len(), 9);
+        assert!(!codes.contains(&0) && !codes.contains(&2));
+    }
+
+    #[test]
+    fn test_is_transient() {
+        let http = |status| Error::Http {
+            status,
+            body: String::new(),
+        };
+        assert!(http(StatusCode::SERVICE_UNAVAILABLE).is_transient());
+        assert!(!http(StatusCode::NOT_FOUND).is_transient());
+        assert!(Error::ComponentUnreachable("connection reset".into()).is_transient());
+        assert!(!Error::BackupFailed("partial".into()).is_transient());
+    }
+}
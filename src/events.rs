@@ -0,0 +1,119 @@
+use std::fmt::Debug;
+
+use k8s_openapi::NamespaceResourceScope;
+use kube::{
+    api::{ListParams, Patch, PatchParams},
+    runtime::events::{Event, EventType, Recorder},
+    Api, Resource, ResourceExt,
+};
+use serde::de::DeserializeOwned;
+use serde_json::json;
+use tracing::warn;
+
+use crate::common::Cluster;
+
+/// ID of the most recent backup that completed while the object was running.
+pub(crate) const LAST_BACKUP_ID: &str = "c8-backup/last-backup-id";
+/// ID of the restore that scaled the object down, removed once it is scaled up again.
+pub(crate) const RESTORING: &str = "c8-backup/restoring";
+
+/// Kubernetes rejects events with longer notes.
+const MAX_NOTE_LENGTH: usize = 1024;
+
+/// Publishes an event regarding the object so that `kubectl describe` shows it. The event refers
+/// to the object by its UID, so it is not shown for a later object of the same name. Failures,
+/// such as missing permissions for `events.k8s.io`, are only logged.
+pub(crate) async fn publish<K>(
+    cluster: &Cluster,
+    object: &K,
+    type_: EventType,
+    action: &str,
+    reason: &str,
+    note: String,
+) where
+    K: Resource<DynamicType = ()>,
+{
+    let reference = object.object_ref(&());
+    let event = Event {
+        type_,
+        reason: reason.to_string(),
+        note: Some(truncate(note, MAX_NOTE_LENGTH)),
+        action: action.to_string(),
+        secondary: None,
+    };
+    if let Err(e) = Recorder::new(cluster.kube.clone(), "c8-backup".into())
+        .publish(&event, &reference)
+        .await
+    {
+        warn!(
+            error = %e,
+            "Could not publish event {} for {} {}",
+            reason,
+            K::kind(&()),
+            object.name_any()
+        );
+    }
+}
+
+/// Sets the annotation of the object, or removes it if `value` is `None`. Failures are only
+/// logged.
+pub(crate) async fn annotate<K>(cluster: &Cluster, name: &str, key: &str, value: Option<&str>)
+where
+    K: Resource<Scope = NamespaceResourceScope, DynamicType = ()>
+        + Clone
+        + DeserializeOwned
+        + Debug,
+{
+    let api: Api<K> = Api::default_namespaced(cluster.kube.clone());
+    let patch = json!({"metadata": {"annotations": {key: value}}});
+    if let Err(e) = api
+        .patch(name, &PatchParams::default(), &Patch::Merge(&patch))
+        .await
+    {
+        warn!(error = %e, "Could not annotate {} {}", K::kind(&()), name);
+    }
+}
+
+/// Objects with the labels. Failures are only logged, as events and annotations are
+/// informational.
+pub(crate) async fn objects<K>(cluster: &Cluster, labels: &str) -> Vec<K>
+where
+    K: Resource<Scope = NamespaceResourceScope, DynamicType = ()>
+        + Clone
+        + DeserializeOwned
+        + Debug,
+{
+    let api: Api<K> = Api::default_namespaced(cluster.kube.clone());
+    match api.list(&ListParams::default().labels(labels)).await {
+        Ok(list) => list.items,
+        Err(e) => {
+            warn!(error = %e, "Could not list {} with labels {}", K::kind(&()), labels);
+            vec![]
+        }
+    }
+}
+
+fn truncate(mut s: String, max_length: usize) -> String {
+    if s.len() > max_length {
+        let mut end = max_length - '…'.len_utf8();
+        while !s.is_char_boundary(end) {
+            end -= 1;
+        }
+        s.truncate(end);
+        s.push('…');
+    }
+    s
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_truncate() {
+        assert_eq!(truncate("short".to_string(), 10), "short");
+        let truncated = truncate("ä".repeat(10), 10);
+        assert!(truncated.len() <= 10);
+        assert_eq!(truncated, "äää…");
+    }
+}
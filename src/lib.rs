@@ -0,0 +1,244 @@
+#![recursion_limit = "256"]
+//! Backup and restore of Camunda Platform 8 deployments.
+//!
+//! [`C8Backup`] drives the components of one deployment. The `c8-backup` binary is a thin
+//! command line wrapper around it.
+
+mod catalog;
+mod check;
+mod common;
+mod create;
+mod daemon;
+mod delete;
+mod describe;
+mod elasticsearch;
+pub mod error;
+mod events;
+mod external;
+mod list;
+mod metrics;
+mod notify;
+mod opensearch;
+mod operate;
+mod pin;
+mod prefixes;
+pub mod profile;
+mod restore;
+mod settings;
+mod templates;
+pub mod types;
+mod zeebe;
+
+pub use common::RetryPolicy;
+pub use error::Error;
+
+use types::{
+    BackupCheck, BackupDescription, BackupList, CatalogArgs, CatalogEntry, ComponentSettings,
+    CreateArgs, CreatedBackup, DaemonArgs, DeletedBackup, IndexPrefixArgs, IndexRestoreArgs,
+    LabelSelector, NotifyArgs, RestoredBackup, SearchArgs, StorageMode,
+};
+
+/// Settings shared by all operations of a [`C8Backup`] client.
+#[derive(Debug, Clone, Default)]
+pub struct Config {
+    pub storage_mode: StorageMode,
+    pub search: SearchArgs,
+    pub index_prefixes: IndexPrefixArgs,
+    /// Applies to all requests of this client
+    pub retry: RetryPolicy,
+    /// ConfigMap that records created backups
+    pub catalog: CatalogArgs,
+    /// Webhooks notified when `create`, `restore`, `delete` and pruning start, succeed or fail
+    pub notify: NotifyArgs,
+    /// Where the components are found
+    pub components: ComponentSettings,
+}
+
+/// Client for backing up and restoring the Camunda Platform 8 deployment in one namespace.
+#[derive(Clone)]
+pub struct C8Backup {
+    cluster: common::Cluster,
+    namespace: String,
+    config: Config,
+}
+
+impl C8Backup {
+    /// Connects with the inferred Kubernetes configuration, using `namespace` instead of the
+    /// configured default namespace if given.
+    pub async fn new(namespace: Option<&str>, config: Config) -> Result<Self, Error> {
+        let mut kube_config = kube::Config::infer()
+            .await
+            .map_err(kube::Error::InferConfig)?;
+        if let Some(namespace) = namespace {
+            kube_config.default_namespace = namespace.to_string();
+        }
+        Ok(Self::with_client(
+            kube::Client::try_from(kube_config)?,
+            config,
+        ))
+    }
+
+    /// Uses an existing client, the deployment must be in its default namespace.
+    pub fn with_client(kube: kube::Client, config: Config) -> Self {
+        Self {
+            namespace: kube.default_namespace().to_string(),
+            cluster: common::Cluster::new(kube, config.components.clone(), config.retry),
+            config,
+        }
+    }
+
+    pub fn namespace(&self) -> &str {
+        &self.namespace
+    }
+
+    pub fn config(&self) -> &Config {
+        &self.config
+    }
+
+    /// Lists the backups of all components and finds the most recent usable one.
+    pub async fn list(&self) -> Result<BackupList, Error> {
+        list::list(&self.cluster, self.config.storage_mode, self.catalog()).await
+    }
+
+    /// Checks that a usable backup younger than `max_age` exists and that no backup younger than
+    /// `max_age` failed.
+    pub async fn check(&self, max_age: std::time::Duration) -> Result<BackupCheck, Error> {
+        check::check(
+            &self.cluster,
+            self.config.storage_mode,
+            self.catalog(),
+            max_age,
+        )
+        .await
+    }
+
+    /// Shows the state of one backup in every component and its labels and note.
+    pub async fn describe(&self, backup_id: u64) -> Result<BackupDescription, Error> {
+        describe::describe(
+            &self.cluster,
+            self.config.storage_mode,
+            self.catalog(),
+            backup_id,
+        )
+        .await
+    }
+
+    /// Takes a new backup of all components, labeled as given.
+    pub async fn create(&self, args: &CreateArgs) -> Result<CreatedBackup, Error> {
+        self.notified(
+            "create",
+            None,
+            create::create(
+                &self.cluster,
+                self.config.storage_mode,
+                &self.config.search,
+                &self.config.index_prefixes,
+                self.catalog(),
+                args,
+            ),
+        )
+        .await
+    }
+
+    /// Restores the most recent usable backup, or the most recent usable one with the `label`.
+    /// In RDBMS mode, `to` and `backup_id` select a point in time or a specific backup instead.
+    pub async fn restore(
+        &self,
+        to: Option<String>,
+        backup_id: Option<u64>,
+        label: Option<&LabelSelector>,
+        index_restore: &IndexRestoreArgs,
+    ) -> Result<RestoredBackup, Error> {
+        restore::check_restore_args(self.config.storage_mode, to.as_deref(), backup_id, label)?;
+        self.notified(
+            "restore",
+            backup_id,
+            restore::restore(
+                &self.cluster,
+                self.config.storage_mode,
+                to,
+                backup_id,
+                &self.config.search,
+                &self.config.index_prefixes,
+                index_restore,
+                self.catalog(),
+                label,
+            ),
+        )
+        .await
+    }
+
+    /// Deletes a backup of all components, including the snapshots it consists of. Pinned backups
+    /// are refused with [`Error::Pinned`].
+    pub async fn delete(&self, backup_id: u64) -> Result<DeletedBackup, Error> {
+        self.notified(
+            "delete",
+            Some(backup_id),
+            delete::delete(
+                &self.cluster,
+                self.config.storage_mode,
+                &self.config.search,
+                &self.config.index_prefixes,
+                self.catalog(),
+                backup_id,
+            ),
+        )
+        .await
+    }
+
+    /// Protects a backup from [`C8Backup::delete`] and pruning by the daemon.
+    pub async fn pin(&self, backup_id: u64) -> Result<CatalogEntry, Error> {
+        pin::set_pinned(
+            &self.cluster,
+            self.config.storage_mode,
+            &self.config.search,
+            &self.config.index_prefixes,
+            self.catalog(),
+            backup_id,
+            true,
+        )
+        .await
+    }
+
+    /// Allows deleting and pruning a pinned backup again.
+    pub async fn unpin(&self, backup_id: u64) -> Result<CatalogEntry, Error> {
+        pin::set_pinned(
+            &self.cluster,
+            self.config.storage_mode,
+            &self.config.search,
+            &self.config.index_prefixes,
+            self.catalog(),
+            backup_id,
+            false,
+        )
+        .await
+    }
+
+    /// Creates backups on a cron schedule until interrupted, optionally pruning old backups
+    /// after each successful one.
+    pub async fn daemon(&self, args: &DaemonArgs) -> Result<(), Error> {
+        daemon::daemon(self, args).await
+    }
+
+    fn catalog(&self) -> &str {
+        &self.config.catalog.catalog_configmap
+    }
+
+    /// Runs an operation, notifying the configured webhooks when it starts, succeeds or fails.
+    pub(crate) async fn notified<T: notify::Outcome>(
+        &self,
+        operation: &'static str,
+        backup_id: Option<u64>,
+        run: impl std::future::Future<Output = Result<T, Error>>,
+    ) -> Result<T, Error> {
+        notify::notified(
+            &self.config.notify,
+            &self.namespace,
+            self.config.storage_mode,
+            operation,
+            backup_id,
+            run,
+        )
+        .await
+    }
+}
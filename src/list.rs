@@ -1,18 +1,18 @@
-use std::{
-    collections::{BTreeSet, HashMap},
-    error::Error,
-};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
 
-use chrono::Utc;
-use chrono::{LocalResult, TimeZone};
+use chrono::{DateTime, Datelike, TimeDelta, Utc};
 
 use chrono_humanize::HumanTime;
 use tracing::{info, warn};
 
 use crate::{
+    catalog,
+    common::Cluster,
+    error::Error,
     operate,
     types::{
-        BackupDescriptor, BackupState, OperateDetails, RuntimeBackupInfo, StorageMode, ZeebeDetails,
+        BackupDescriptor, BackupList, BackupState, CatalogEntry, LabelSelector, OperateDetails,
+        RuntimeBackupInfo, StorageMode, ZeebeDetails,
     },
     zeebe,
 };
@@ -41,18 +41,28 @@ impl BackupEntry for RuntimeBackupInfo {
     }
 }
 
-pub(crate) async fn list(storage_mode: StorageMode) -> Result<(), Box<dyn Error>> {
-    let kube = kube::Client::try_default().await?;
+pub(crate) async fn list(
+    cluster: &Cluster,
+    storage_mode: StorageMode,
+    catalog: &str,
+) -> Result<BackupList, Error> {
+    let catalog = match catalog::read_all(cluster, catalog).await {
+        Ok(entries) => entries,
+        Err(e) => {
+            warn!("Could not read the backup catalog {}: {}", catalog, e);
+            vec![]
+        }
+    };
     match storage_mode {
-        StorageMode::Elasticsearch => list_es(&kube).await,
-        StorageMode::Rdbms => list_rdbms(&kube).await,
+        StorageMode::Elasticsearch | StorageMode::Opensearch => list_es(cluster, catalog).await,
+        StorageMode::Rdbms => list_rdbms(cluster, catalog).await,
     }
 }
 
-#[tracing::instrument(skip(kube), err)]
-async fn list_es(kube: &kube::Client) -> Result<(), Box<dyn Error>> {
-    let zeebe_backups: Vec<BackupDescriptor<ZeebeDetails>> = zeebe::list_backups(kube).await?;
-    let operate_backups = operate::list_backups(kube).await?;
+#[tracing::instrument(skip(cluster, catalog), err)]
+async fn list_es(cluster: &Cluster, mut catalog: Vec<CatalogEntry>) -> Result<BackupList, Error> {
+    let zeebe_backups: Vec<BackupDescriptor<ZeebeDetails>> = zeebe::list_backups(cluster).await?;
+    let operate_backups = operate::list_backups(cluster).await?;
 
     tracing::info_span!("Zeebe").in_scope(|| {
         print_stats("backups", &zeebe_backups);
@@ -60,30 +70,61 @@ async fn list_es(kube: &kube::Client) -> Result<(), Box<dyn Error>> {
     tracing::info_span!("Operate").in_scope(|| {
         print_stats("backups", &operate_backups);
     });
+    tracing::info_span!("Catalog").in_scope(|| {
+        print_catalog(
+            &catalog,
+            &[&ids(&zeebe_backups), &ids(&operate_backups)],
+            &in_progress(&zeebe_backups)
+                .into_iter()
+                .chain(in_progress(&operate_backups))
+                .collect(),
+        );
+    });
+    catalog.retain(|e| !e.reserved);
 
-    match find_most_recent_usable(&zeebe_backups, &operate_backups) {
-        Some(id) => log_backup_timestamp("The most recent usable backup", id),
+    let most_recent_usable =
+        find_most_recent_recoverable(&zeebe_backups, &operate_backups, &catalog);
+    match most_recent_usable {
+        Some(id) => log_backup_timestamp("The most recent usable backup", id, &catalog),
         None => warn!("No usable backups found"),
     }
 
-    Ok(())
+    Ok(BackupList {
+        zeebe: zeebe_backups,
+        operate: operate_backups,
+        catalog,
+        most_recent_usable,
+        ..Default::default()
+    })
 }
 
-#[tracing::instrument(skip(kube), err)]
-async fn list_rdbms(kube: &kube::Client) -> Result<(), Box<dyn Error>> {
-    let runtime_backups = zeebe::list_runtime_backups(kube).await?;
+#[tracing::instrument(skip(cluster, catalog), err)]
+async fn list_rdbms(
+    cluster: &Cluster,
+    mut catalog: Vec<CatalogEntry>,
+) -> Result<BackupList, Error> {
+    let runtime_backups = zeebe::list_runtime_backups(cluster).await?;
 
     tracing::info_span!("Runtime Backups").in_scope(|| {
         print_stats("runtime backups", &runtime_backups);
     });
+    tracing::info_span!("Catalog").in_scope(|| {
+        print_catalog(
+            &catalog,
+            &[&ids(&runtime_backups)],
+            &in_progress(&runtime_backups),
+        );
+    });
+    catalog.retain(|e| !e.reserved);
 
-    match find_most_recent_runtime_backup(&runtime_backups) {
-        Some(id) => log_backup_timestamp("The most recent completed runtime backup", id),
+    let most_recent_usable = find_most_recent_runtime_backup(&runtime_backups);
+    match most_recent_usable {
+        Some(id) => log_backup_timestamp("The most recent completed runtime backup", id, &catalog),
         None => warn!("No completed runtime backups found"),
     }
 
     // Show checkpoint state
-    match zeebe::get_backup_state(kube).await {
+    match zeebe::get_backup_state(cluster).await {
         Ok(state) => {
             info!("Backup ranges: {} partition(s)", state.ranges.len());
         }
@@ -92,12 +133,17 @@ async fn list_rdbms(kube: &kube::Client) -> Result<(), Box<dyn Error>> {
         }
     }
 
-    Ok(())
+    Ok(BackupList {
+        runtime: runtime_backups,
+        catalog,
+        most_recent_usable,
+        ..Default::default()
+    })
 }
 
-fn log_backup_timestamp(label: &str, backup_id: u64) {
+fn log_backup_timestamp(label: &str, backup_id: u64, catalog: &[CatalogEntry]) {
     info!("{} is {}", label, backup_id);
-    if let LocalResult::Single(date) = Utc.timestamp_opt(backup_id as i64, 0) {
+    if let Some(date) = created_at(backup_id, catalog, Utc::now()) {
         info!(
             "This backup was created {} at {}",
             HumanTime::from(date),
@@ -106,10 +152,34 @@ fn log_backup_timestamp(label: &str, backup_id: u64) {
     }
 }
 
+/// Creation time of the backup as recorded in the catalog. Backups that are not recorded fall
+/// back to their ID if it is a plausible timestamp in seconds or milliseconds since the epoch,
+/// as chosen by the `seconds`, `millis` and `monotonic` ID strategies.
+pub(crate) fn created_at(
+    backup_id: u64,
+    catalog: &[CatalogEntry],
+    now: DateTime<Utc>,
+) -> Option<DateTime<Utc>> {
+    let recorded = catalog
+        .iter()
+        .find(|e| e.backup_id == backup_id)
+        .and_then(|e| e.created_at.as_deref())
+        .and_then(|created_at| DateTime::parse_from_rfc3339(created_at).ok());
+    if let Some(recorded) = recorded {
+        return Some(recorded.with_timezone(&Utc));
+    }
+    // Camunda 8 backups did not exist before 2020, and monotonic IDs may run slightly ahead.
+    let plausible = |date: &DateTime<Utc>| date.year() >= 2020 && *date <= now + TimeDelta::days(1);
+    let id = i64::try_from(backup_id).ok()?;
+    DateTime::from_timestamp(id, 0)
+        .filter(plausible)
+        .or_else(|| DateTime::from_timestamp_millis(id).filter(plausible))
+}
+
 #[tracing::instrument(level = "debug")]
 pub fn find_most_recent_usable(
-    zeebe: &Vec<BackupDescriptor<ZeebeDetails>>,
-    operate: &Vec<BackupDescriptor<OperateDetails>>,
+    zeebe: &[BackupDescriptor<ZeebeDetails>],
+    operate: &[BackupDescriptor<OperateDetails>],
 ) -> Option<u64> {
     let zeebe: BTreeSet<u64> = zeebe
         .iter()
@@ -125,6 +195,30 @@ pub fn find_most_recent_usable(
     zeebe.intersection(&operate).last().copied()
 }
 
+/// Like [`find_most_recent_usable`], but also accepts completed Zeebe backups that Operate no
+/// longer knows if the catalog recorded their Operate snapshots.
+pub fn find_most_recent_recoverable(
+    zeebe: &[BackupDescriptor<ZeebeDetails>],
+    operate: &[BackupDescriptor<OperateDetails>],
+    catalog: &[CatalogEntry],
+) -> Option<u64> {
+    let operate_known = ids(operate);
+    let cataloged = zeebe
+        .iter()
+        .filter(|b| b.state == BackupState::Completed)
+        .map(|b| b.backup_id)
+        .filter(|id| {
+            !operate_known.contains(id)
+                && catalog
+                    .iter()
+                    .any(|e| e.backup_id == *id && !e.reserved && !e.operate_snapshots.is_empty())
+        });
+    find_most_recent_usable(zeebe, operate)
+        .into_iter()
+        .chain(cataloged)
+        .max()
+}
+
 pub fn find_most_recent_runtime_backup(backups: &[RuntimeBackupInfo]) -> Option<u64> {
     backups
         .iter()
@@ -133,6 +227,91 @@ pub fn find_most_recent_runtime_backup(backups: &[RuntimeBackupInfo]) -> Option<
         .max()
 }
 
+/// IDs of the backups whose catalog entry matches the label.
+pub(crate) fn labeled(catalog: &[CatalogEntry], label: &LabelSelector) -> BTreeSet<u64> {
+    catalog
+        .iter()
+        .filter(|e| label.matches(&e.labels))
+        .map(|e| e.backup_id)
+        .collect()
+}
+
+fn ids<T: BackupEntry>(backups: &[T]) -> BTreeSet<u64> {
+    backups.iter().map(|b| b.backup_id()).collect()
+}
+
+fn in_progress<T: BackupEntry>(backups: &[T]) -> BTreeSet<u64> {
+    backups
+        .iter()
+        .filter(|b| b.state() == BackupState::InProgress)
+        .map(|b| b.backup_id())
+        .collect()
+}
+
+/// IDs that are reserved in the catalog although no component takes their backup, because the
+/// backup was interrupted before it could release the reservation.
+fn stale_reservations(catalog: &[CatalogEntry], in_progress: &BTreeSet<u64>) -> Vec<u64> {
+    catalog
+        .iter()
+        .filter(|e| e.reserved && !in_progress.contains(&e.backup_id))
+        .map(|e| e.backup_id)
+        .collect()
+}
+
+/// Logs the catalog and warns about recorded backups that a component no longer knows and about
+/// stale reservations.
+fn print_catalog(catalog: &[CatalogEntry], known: &[&BTreeSet<u64>], in_progress: &BTreeSet<u64>) {
+    for backup_id in stale_reservations(catalog, in_progress) {
+        warn!(
+            "Backup ID {} is reserved in the catalog, but its backup is not in progress. It was \
+             probably interrupted, remove it with `delete {}`",
+            backup_id, backup_id
+        );
+    }
+    let catalog = catalog.iter().filter(|e| !e.reserved).collect::<Vec<_>>();
+    info!("{} backups recorded", catalog.len());
+    for entry in catalog {
+        if entry.pinned || !entry.labels.is_empty() || entry.note.is_some() {
+            info!(
+                "Backup {}{}{}{}",
+                entry.backup_id,
+                if entry.pinned { " (pinned)" } else { "" },
+                format_labels(&entry.labels),
+                entry
+                    .note
+                    .as_ref()
+                    .map(|n| format!(": {}", n))
+                    .unwrap_or_default()
+            );
+        }
+        if known.iter().any(|ids| !ids.contains(&entry.backup_id)) {
+            warn!(
+                "Backup {} created at {} is recorded but not known to all components",
+                entry.backup_id,
+                entry.created_at.as_deref().unwrap_or("unknown time")
+            );
+        }
+    }
+}
+
+/// Formats labels as ` [key=value, key]`, leaving out empty values.
+pub(crate) fn format_labels(labels: &BTreeMap<String, String>) -> String {
+    if labels.is_empty() {
+        return String::new();
+    }
+    let labels = labels
+        .iter()
+        .map(|(k, v)| {
+            if v.is_empty() {
+                k.clone()
+            } else {
+                format!("{}={}", k, v)
+            }
+        })
+        .collect::<Vec<_>>();
+    format!(" [{}]", labels.join(", "))
+}
+
 fn print_stats<T: BackupEntry>(label: &str, backups: &[T]) {
     let backups_by_state =
         backups
@@ -247,6 +426,118 @@ mod tests {
         assert_eq!(find_most_recent_usable(&zeebe, &operate), Some(1));
     }
 
+    #[test]
+    fn test_find_most_recent_recoverable_uses_catalog() {
+        let zeebe = vec![
+            BackupDescriptor {
+                backup_id: 1,
+                state: BackupState::Completed,
+                details: vec![],
+            },
+            BackupDescriptor {
+                backup_id: 2,
+                state: BackupState::Completed,
+                details: vec![],
+            },
+            BackupDescriptor {
+                backup_id: 3,
+                state: BackupState::Completed,
+                details: vec![],
+            },
+        ];
+        let operate = vec![
+            BackupDescriptor {
+                backup_id: 1,
+                state: BackupState::Completed,
+                details: vec![],
+            },
+            BackupDescriptor {
+                backup_id: 3,
+                state: BackupState::Failed,
+                details: vec![],
+            },
+        ];
+        let entry = |backup_id| CatalogEntry {
+            backup_id,
+            operate_snapshots: vec![format!("camunda_operate_{}_8.5.0_part_1_of_1", backup_id)],
+            ..Default::default()
+        };
+        assert_eq!(find_most_recent_recoverable(&zeebe, &operate, &[]), Some(1));
+        // Operate forgot backup 2, but a failed Operate backup 3 is not recoverable
+        assert_eq!(
+            find_most_recent_recoverable(&zeebe, &operate, &[entry(2), entry(3)]),
+            Some(2)
+        );
+    }
+
+    #[test]
+    fn test_stale_reservations() {
+        let catalog = vec![
+            CatalogEntry {
+                backup_id: 1,
+                ..Default::default()
+            },
+            CatalogEntry {
+                backup_id: 2,
+                reserved: true,
+                ..Default::default()
+            },
+            CatalogEntry {
+                backup_id: 3,
+                reserved: true,
+                ..Default::default()
+            },
+        ];
+        assert_eq!(
+            stale_reservations(&catalog, &BTreeSet::from([1, 3])),
+            vec![2]
+        );
+    }
+
+    #[test]
+    fn test_created_at() {
+        let now = DateTime::from_timestamp(1_700_000_000, 0).unwrap();
+        let catalog = vec![CatalogEntry {
+            backup_id: 42,
+            created_at: Some("2023-05-04T15:37:00+00:00".to_string()),
+            ..Default::default()
+        }];
+        assert_eq!(
+            created_at(42, &catalog, now),
+            DateTime::from_timestamp(1_683_214_620, 0)
+        );
+        assert_eq!(created_at(43, &catalog, now), None);
+        assert_eq!(
+            created_at(1_683_214_620, &catalog, now),
+            DateTime::from_timestamp(1_683_214_620, 0)
+        );
+        assert_eq!(
+            created_at(1_683_214_620_500, &catalog, now),
+            DateTime::from_timestamp_millis(1_683_214_620_500)
+        );
+    }
+
+    #[test]
+    fn test_labeled() {
+        let catalog = vec![
+            CatalogEntry {
+                backup_id: 1,
+                labels: BTreeMap::from([("reason".to_string(), "pre-upgrade".to_string())]),
+                ..Default::default()
+            },
+            CatalogEntry {
+                backup_id: 2,
+                ..Default::default()
+            },
+        ];
+        let label = "reason=pre-upgrade".parse().unwrap();
+        assert_eq!(labeled(&catalog, &label), BTreeSet::from([1]));
+        assert_eq!(
+            format_labels(&catalog[0].labels),
+            " [reason=pre-upgrade]".to_string()
+        );
+    }
+
     #[test]
     fn test_find_most_recent_runtime_backup_empty() {
         let backups: Vec<RuntimeBackupInfo> = vec![];
@@ -1,45 +1,126 @@
-use clap::{Parser, Subcommand};
+#![recursion_limit = "256"]
+
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    path::{Path, PathBuf},
+    process::ExitCode,
+};
+
+use clap::{
+    builder::ArgPredicate, Arg, ArgAction, Command, CommandFactory, FromArgMatches, Parser,
+    Subcommand,
+};
 use tracing::Level;
 use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt, EnvFilter, Registry};
 use tracing_tree::HierarchicalLayer;
 
-mod common;
-mod create;
-mod elasticsearch;
-mod list;
-mod operate;
-mod restore;
-pub mod types;
-mod zeebe;
-
-use types::StorageMode;
+use c8_backup::{
+    profile::{self, Profile},
+    types::{
+        CatalogArgs, ComponentSettings, CreateArgs, DaemonArgs, IndexPrefixArgs, IndexRestoreArgs,
+        LabelSelector, NotifyArgs, RetryArgs, SearchArgs, StorageMode,
+    },
+    C8Backup, Config, Error,
+};
 
 #[derive(Subcommand)]
+#[allow(clippy::large_enum_variant)]
 enum Commands {
-    List,
-    Create,
+    List {
+        #[command(flatten)]
+        retry: RetryArgs,
+    },
+    /// Check that a recent usable backup exists, exiting with 0 (OK), 1 (WARNING) or 2 (CRITICAL)
+    Check {
+        /// Maximum age of the most recent usable backup, e.g. `24h` or `90m`
+        #[arg(long, value_parser = humantime::parse_duration)]
+        max_age: std::time::Duration,
+        #[command(flatten)]
+        retry: RetryArgs,
+    },
+    /// Show the state of a backup in every component, its labels and note
+    Describe {
+        backup_id: u64,
+        #[command(flatten)]
+        retry: RetryArgs,
+    },
+    Create {
+        #[command(flatten)]
+        create: CreateArgs,
+        #[command(flatten)]
+        retry: RetryArgs,
+    },
     Restore {
         /// Point-in-time restore target (ISO 8601 timestamp, RDBMS mode only)
         #[arg(long)]
         to: Option<String>,
-        /// Explicit backup ID to restore from
+        /// Explicit backup ID to restore from (RDBMS mode only)
         #[arg(long)]
         backup_id: Option<u64>,
+        /// Restore the most recent usable backup with the label, `KEY` or `KEY=VALUE`
+        #[arg(long)]
+        label: Option<LabelSelector>,
+        #[command(flatten)]
+        index_restore: IndexRestoreArgs,
+        #[command(flatten)]
+        retry: RetryArgs,
+    },
+    /// Delete a backup of all components, including its snapshots
+    Delete {
+        backup_id: u64,
+        #[command(flatten)]
+        retry: RetryArgs,
+    },
+    /// Protect a backup from deletion and pruning
+    Pin {
+        backup_id: u64,
+        #[command(flatten)]
+        retry: RetryArgs,
+    },
+    /// Allow deleting and pruning a pinned backup again
+    Unpin {
+        backup_id: u64,
+        #[command(flatten)]
+        retry: RetryArgs,
+    },
+    /// Keep running and create backups on a schedule
+    Daemon {
+        #[command(flatten)]
+        daemon: DaemonArgs,
+        #[command(flatten)]
+        retry: RetryArgs,
     },
 }
 
 #[derive(Parser)]
 #[command(author, version, about, long_about = None)]
 struct Cli {
+    /// Configuration file with named profiles [default: c8-backup.toml if it exists]
+    #[arg(long, global = true, env = "C8_BACKUP_CONFIG")]
+    config: Option<PathBuf>,
+    /// Profile of the configuration file whose settings are used as defaults [default: default]
+    #[arg(long, global = true, env = "C8_BACKUP_PROFILE")]
+    profile: Option<String>,
+    /// Namespace of the Camunda deployment [default: namespace of the current context]
+    #[arg(long, short, global = true)]
+    namespace: Option<String>,
     /// Secondary storage type of the Camunda deployment
     #[arg(long, value_enum, default_value_t = StorageMode::Elasticsearch)]
     storage_mode: StorageMode,
+    #[command(flatten)]
+    index_prefixes: IndexPrefixArgs,
+    #[command(flatten)]
+    search: SearchArgs,
+    #[command(flatten)]
+    catalog: CatalogArgs,
+    #[command(flatten)]
+    notify: NotifyArgs,
     #[command(subcommand)]
     command: Commands,
 }
 
 #[tokio::main]
-async fn main() -> Result<(), Box<dyn std::error::Error>> {
+async fn main() -> ExitCode {
     Registry::default()
         .with(
             EnvFilter::builder()
@@ -52,13 +133,276 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
                 .with_bracketed_fields(true),
         )
         .init();
-    let cli = Cli::parse();
+    let result = async {
+        let (cli, components) = parse()?;
+        run(cli, components).await
+    }
+    .await;
+    match result {
+        Ok(code) => code,
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            ExitCode::from(e.exit_code())
+        }
+    }
+}
+
+/// Parses the command line with the defaults of the selected profile.
+fn parse() -> Result<(Cli, ComponentSettings), Error> {
+    // The profile provides the defaults of the other arguments, so it is read before parsing them.
+    let args = std::env::args().collect::<Vec<_>>();
+    let config = profile::find_option(&args, "config").or(std::env::var("C8_BACKUP_CONFIG").ok());
+    let name = profile::find_option(&args, "profile").or(std::env::var("C8_BACKUP_PROFILE").ok());
+    let profile = profile::load(config.as_deref().map(Path::new), name.as_deref())?;
+    let matches = with_profile_defaults(Cli::command(), &profile)?.get_matches();
+    let cli = Cli::from_arg_matches(&matches).unwrap_or_else(|e| e.exit());
+    Ok((cli, profile.components))
+}
+
+/// Uses the options of the profile as defaults of the command line arguments with the same long
+/// name, so that flags and environment variables override them. Options of a subcommand are only
+/// taken from its table, except for those that every subcommand accepts, such as
+/// `retry-attempts`.
+fn with_profile_defaults(mut command: Command, profile: &Profile) -> Result<Command, Error> {
+    let values = profile.option_values()?;
+    let subcommand_values = profile.subcommand_option_values()?;
+    let mut used = BTreeSet::new();
+    command = set_defaults(command, &values, &mut used);
+
+    let subcommands = command
+        .get_subcommands()
+        .map(|s| (s.get_name().to_string(), long_names(s)))
+        .collect::<BTreeMap<_, _>>();
+    if let Some(name) = subcommand_values
+        .keys()
+        .find(|name| !subcommands.contains_key(*name))
+    {
+        return Err(format!("Unknown subcommand `{}` in profile", name).into());
+    }
+    let shared = values
+        .iter()
+        .filter(|(name, _)| subcommands.values().all(|names| names.contains(*name)))
+        .map(|(name, values)| (name.clone(), values.clone()))
+        .collect::<BTreeMap<_, _>>();
+    used.extend(shared.keys().cloned());
+    if let Some(name) = values.keys().find(|name| !used.contains(*name)) {
+        return Err(
+            match subcommands.iter().find(|(_, names)| names.contains(name)) {
+                Some((subcommand, _)) => format!(
+                    "Option `{}` in profile belongs to a subcommand, set it in a table such as \
+                 `[profiles.<name>.{}]`",
+                    name, subcommand
+                ),
+                None => format!("Unknown option `{}` in profile", name),
+            }
+            .into(),
+        );
+    }
+
+    for name in subcommands.into_keys() {
+        let own = subcommand_values.get(&name).cloned().unwrap_or_default();
+        let mut scoped = shared.clone();
+        scoped.extend(own.clone());
+        let mut used = BTreeSet::new();
+        command = command.mut_subcommand(&name, |s| set_defaults(s, &scoped, &mut used));
+        if let Some(option) = own.keys().find(|option| !used.contains(*option)) {
+            return Err(format!("Unknown option `{}.{}` in profile", name, option).into());
+        }
+    }
+    Ok(command)
+}
+
+fn long_names(command: &Command) -> BTreeSet<String> {
+    command
+        .get_arguments()
+        .filter_map(|a| Some(a.get_long()?.to_string()))
+        .collect()
+}
+
+fn set_defaults(
+    mut command: Command,
+    values: &BTreeMap<String, Vec<String>>,
+    used: &mut BTreeSet<String>,
+) -> Command {
+    let args = command
+        .get_arguments()
+        .filter_map(|a| {
+            let flag = matches!(a.get_action(), ArgAction::SetTrue);
+            Some((
+                a.get_id().clone(),
+                a.get_long()?.to_string(),
+                flag,
+                a.is_global_set(),
+            ))
+        })
+        .collect::<Vec<_>>();
+    for (id, long, flag, global) in args {
+        let Some(values) = values.get(&long) else {
+            continue;
+        };
+        used.insert(long.clone());
+        // A required argument is satisfied by the profile.
+        command = command.mut_arg(&id, |a| a.default_values(values.clone()).required(false));
+        // A flag that the profile turns on could not be turned off on the command line otherwise.
+        if flag && values.iter().any(|v| v == "true") {
+            let negated = format!("no-{}", long);
+            command = command
+                .mut_arg(&id, |a| {
+                    a.default_value_if(negated.clone(), ArgPredicate::IsPresent, Some("false"))
+                })
+                .arg(
+                    Arg::new(negated.clone())
+                        .long(negated)
+                        .help(format!("Turn off --{}, which the profile turns on", long))
+                        .action(ArgAction::SetTrue)
+                        .global(global)
+                        .conflicts_with(id),
+                );
+        }
+    }
+    command
+}
+
+async fn run(cli: Cli, components: ComponentSettings) -> Result<ExitCode, Error> {
+    let retry = match &cli.command {
+        Commands::List { retry }
+        | Commands::Check { retry, .. }
+        | Commands::Describe { retry, .. }
+        | Commands::Create { retry, .. }
+        | Commands::Restore { retry, .. }
+        | Commands::Delete { retry, .. }
+        | Commands::Pin { retry, .. }
+        | Commands::Unpin { retry, .. }
+        | Commands::Daemon { retry, .. } => retry.into(),
+    };
+    let config = Config {
+        storage_mode: cli.storage_mode,
+        search: cli.search,
+        index_prefixes: cli.index_prefixes,
+        retry,
+        catalog: cli.catalog,
+        notify: cli.notify,
+        components,
+    };
+    let client = C8Backup::new(cli.namespace.as_deref(), config).await?;
 
     match cli.command {
-        Commands::List => list::list(cli.storage_mode).await,
-        Commands::Create => create::create(cli.storage_mode).await,
-        Commands::Restore { to, backup_id } => {
-            restore::restore(cli.storage_mode, to, backup_id).await
+        Commands::List { .. } => {
+            client.list().await?;
+        }
+        Commands::Check { max_age, .. } => {
+            // Errors are reported as UNKNOWN (3) in the Nagios plugin convention.
+            return Ok(match client.check(max_age).await {
+                Ok(check) => {
+                    println!("{} - {}", check.status, check.summary);
+                    ExitCode::from(check.status.exit_code())
+                }
+                Err(e) => {
+                    println!("UNKNOWN - {}", e);
+                    ExitCode::from(3)
+                }
+            });
+        }
+        Commands::Describe { backup_id, .. } => {
+            client.describe(backup_id).await?;
+        }
+        Commands::Create { create, .. } => {
+            client.create(&create).await?;
+        }
+        Commands::Restore {
+            to,
+            backup_id,
+            label,
+            index_restore,
+            ..
+        } => {
+            client
+                .restore(to, backup_id, label.as_ref(), &index_restore)
+                .await?;
+        }
+        Commands::Delete { backup_id, .. } => {
+            client.delete(backup_id).await?;
+        }
+        Commands::Pin { backup_id, .. } => {
+            client.pin(backup_id).await?;
         }
+        Commands::Unpin { backup_id, .. } => {
+            client.unpin(backup_id).await?;
+        }
+        Commands::Daemon { daemon, .. } => {
+            client.daemon(&daemon).await?;
+        }
+    }
+    Ok(ExitCode::SUCCESS)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse_with(profile: &str, args: &str) -> Result<Cli, String> {
+        let profile: Profile = toml::from_str(profile).unwrap();
+        let command = with_profile_defaults(Cli::command(), &profile).map_err(|e| e.to_string())?;
+        let matches = command
+            .try_get_matches_from(args.split(' '))
+            .map_err(|e| e.kind().to_string())?;
+        Cli::from_arg_matches(&matches).map_err(|e| e.to_string())
+    }
+
+    #[test]
+    fn test_profile_options_are_scoped_to_subcommands() {
+        let profile = "retry-attempts = 10\n[create]\nbackup-id = 5\n";
+        let Commands::Create { create, retry } =
+            parse_with(profile, "c8-backup create").unwrap().command
+        else {
+            panic!("expected create");
+        };
+        assert_eq!(create.backup_id, Some(5));
+        assert_eq!(retry.retry_attempts.get(), 10);
+
+        let Commands::Restore { backup_id, .. } =
+            parse_with(profile, "c8-backup restore").unwrap().command
+        else {
+            panic!("expected restore");
+        };
+        assert_eq!(backup_id, None);
+    }
+
+    #[test]
+    fn test_unscoped_subcommand_options_are_rejected() {
+        assert_eq!(
+            parse_with("allow-yellow = true", "c8-backup restore").err(),
+            Some(
+                "Option `allow-yellow` in profile belongs to a subcommand, set it in a table \
+                 such as `[profiles.<name>.restore]`"
+                    .to_string()
+            )
+        );
+        assert_eq!(
+            parse_with("[restore]\nmax-age = \"1h\"", "c8-backup restore").err(),
+            Some("Unknown option `restore.max-age` in profile".to_string())
+        );
+        assert_eq!(
+            parse_with("[search]\nurl = \"x\"", "c8-backup list").err(),
+            Some("Unknown subcommand `search` in profile".to_string())
+        );
+    }
+
+    #[test]
+    fn test_flags_turned_on_by_profile_can_be_turned_off() {
+        let profile = "[restore]\nallow-yellow = true\n";
+        let allow_yellow = |args| match parse_with(profile, args).map(|cli| cli.command) {
+            Ok(Commands::Restore { index_restore, .. }) => Ok(index_restore.allow_yellow),
+            Ok(_) => panic!("expected restore"),
+            Err(e) => Err(e),
+        };
+        assert_eq!(allow_yellow("c8-backup restore"), Ok(true));
+        assert_eq!(
+            allow_yellow("c8-backup restore --no-allow-yellow"),
+            Ok(false)
+        );
+        assert_eq!(allow_yellow("c8-backup restore --allow-yellow"), Ok(true));
+        assert!(allow_yellow("c8-backup restore --allow-yellow --no-allow-yellow").is_err());
+        assert!(parse_with("", "c8-backup restore --no-allow-yellow").is_err());
     }
 }
@@ -0,0 +1,268 @@
+use std::{
+    collections::BTreeMap,
+    convert::Infallible,
+    fmt::Write,
+    net::SocketAddr,
+    sync::{Arc, Mutex},
+};
+
+use bytes::Bytes;
+use chrono::Utc;
+use http_body_util::Full;
+use hyper::{body::Incoming, server::conn::http1, service::service_fn, Method, Request, Response};
+use hyper_util::rt::TokioIo;
+use tokio::net::TcpListener;
+use tracing::{info, warn};
+
+use crate::{
+    error::Error,
+    list,
+    types::{BackupList, BackupState, CreatedBackup},
+};
+
+/// Backup health of the daemon, served in the Prometheus text format.
+#[derive(Default)]
+pub(crate) struct Metrics {
+    inner: Mutex<Inner>,
+}
+
+#[derive(Default)]
+struct Inner {
+    /// Creation time of the most recent completed backup per component, in epoch seconds
+    last_success: BTreeMap<&'static str, i64>,
+    backups: BTreeMap<(&'static str, &'static str), usize>,
+    last_duration: Option<f64>,
+    last_exporting_paused: Option<f64>,
+    failures: BTreeMap<&'static str, u64>,
+}
+
+impl Metrics {
+    /// Operations whose failures are counted, reported as 0 before they first fail.
+    const OPERATIONS: [&'static str; 3] = ["create", "list", "prune"];
+
+    pub(crate) fn record_list(&self, list: &BackupList) {
+        let mut inner = self.inner.lock().expect("Metrics must not be poisoned");
+        inner.backups.clear();
+        let zeebe = list.zeebe.iter().map(|b| ("zeebe", b.backup_id, b.state));
+        let operate = list
+            .operate
+            .iter()
+            .map(|b| ("operate", b.backup_id, b.state));
+        // Runtime backups are taken by Zeebe in RDBMS mode.
+        let runtime = list.runtime.iter().map(|b| ("zeebe", b.backup_id, b.state));
+        let now = Utc::now();
+        for (component, backup_id, state) in zeebe.chain(operate).chain(runtime) {
+            *inner
+                .backups
+                .entry((component, state_label(state)))
+                .or_default() += 1;
+            if state != BackupState::Completed {
+                continue;
+            }
+            // Backups whose creation time is unknown are left out.
+            if let Some(created_at) = list::created_at(backup_id, &list.catalog, now) {
+                let last = inner.last_success.entry(component).or_default();
+                *last = (*last).max(created_at.timestamp());
+            }
+        }
+    }
+
+    pub(crate) fn record_created(&self, backup: &CreatedBackup) {
+        let mut inner = self.inner.lock().expect("Metrics must not be poisoned");
+        inner.last_duration = Some(backup.duration.as_secs_f64());
+        if let Some(paused) = backup.exporting_paused {
+            inner.last_exporting_paused = Some(paused.as_secs_f64());
+        }
+    }
+
+    pub(crate) fn record_failure(&self, operation: &'static str) {
+        *self
+            .inner
+            .lock()
+            .expect("Metrics must not be poisoned")
+            .failures
+            .entry(operation)
+            .or_default() += 1;
+    }
+
+    fn render(&self) -> String {
+        let inner = self.inner.lock().expect("Metrics must not be poisoned");
+        let mut out = String::new();
+
+        header(
+            &mut out,
+            "c8_backup_last_success_timestamp_seconds",
+            "gauge",
+            "Creation time of the most recent completed backup per component",
+        );
+        for (component, timestamp) in &inner.last_success {
+            let _ = writeln!(
+                out,
+                "c8_backup_last_success_timestamp_seconds{{component=\"{}\"}} {}",
+                component, timestamp
+            );
+        }
+
+        header(
+            &mut out,
+            "c8_backup_backups",
+            "gauge",
+            "Number of backups per component and state",
+        );
+        for ((component, state), count) in &inner.backups {
+            let _ = writeln!(
+                out,
+                "c8_backup_backups{{component=\"{}\",state=\"{}\"}} {}",
+                component, state, count
+            );
+        }
+
+        if let Some(duration) = inner.last_duration {
+            header(
+                &mut out,
+                "c8_backup_duration_seconds",
+                "gauge",
+                "Duration of the most recent successful backup",
+            );
+            let _ = writeln!(out, "c8_backup_duration_seconds {}", duration);
+        }
+        if let Some(paused) = inner.last_exporting_paused {
+            header(
+                &mut out,
+                "c8_backup_exporting_paused_seconds",
+                "gauge",
+                "How long Zeebe exporting was paused during the most recent successful backup",
+            );
+            let _ = writeln!(out, "c8_backup_exporting_paused_seconds {}", paused);
+        }
+
+        header(
+            &mut out,
+            "c8_backup_failures_total",
+            "counter",
+            "Number of failed operations",
+        );
+        for operation in Self::OPERATIONS {
+            let _ = writeln!(
+                out,
+                "c8_backup_failures_total{{operation=\"{}\"}} {}",
+                operation,
+                inner.failures.get(operation).copied().unwrap_or_default()
+            );
+        }
+        out
+    }
+}
+
+fn header(out: &mut String, name: &str, kind: &str, help: &str) {
+    let _ = writeln!(out, "# HELP {} {}", name, help);
+    let _ = writeln!(out, "# TYPE {} {}", name, kind);
+}
+
+fn state_label(state: BackupState) -> &'static str {
+    match state {
+        BackupState::Completed => "COMPLETED",
+        BackupState::Failed => "FAILED",
+        BackupState::InProgress => "IN_PROGRESS",
+        BackupState::Incomplete => "INCOMPLETE",
+        BackupState::DoesNotExist => "DOES_NOT_EXIST",
+        BackupState::Incompatible => "INCOMPATIBLE",
+        BackupState::Deleted => "DELETED",
+    }
+}
+
+/// Serves `/metrics` on `address` in the background.
+#[tracing::instrument(skip(metrics), err)]
+pub(crate) async fn serve(address: SocketAddr, metrics: Arc<Metrics>) -> Result<(), Error> {
+    let listener = TcpListener::bind(address).await?;
+    info!(
+        "Serving metrics on http://{}/metrics",
+        listener.local_addr()?
+    );
+    tokio::spawn(async move {
+        loop {
+            let stream = match listener.accept().await {
+                Ok((stream, _)) => stream,
+                Err(e) => {
+                    warn!(error = %e, "Could not accept metrics connection");
+                    continue;
+                }
+            };
+            let metrics = metrics.clone();
+            tokio::spawn(async move {
+                let service = service_fn(|req| handle(req, &metrics));
+                if let Err(e) = http1::Builder::new()
+                    .serve_connection(TokioIo::new(stream), service)
+                    .await
+                {
+                    warn!(error = %e, "Metrics connection failed");
+                }
+            });
+        }
+    });
+    Ok(())
+}
+
+async fn handle(
+    req: Request<Incoming>,
+    metrics: &Metrics,
+) -> Result<Response<Full<Bytes>>, Infallible> {
+    let response = if req.method() == Method::GET && req.uri().path() == "/metrics" {
+        Response::builder()
+            .header("Content-Type", "text/plain; version=0.0.4")
+            .body(Full::new(Bytes::from(metrics.render())))
+    } else {
+        Response::builder()
+            .status(hyper::StatusCode::NOT_FOUND)
+            .body(Full::default())
+    };
+    Ok(response.expect("static response parts are valid"))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+    use crate::types::BackupDescriptor;
+
+    #[test]
+    fn test_render() {
+        let metrics = Metrics::default();
+        metrics.record_list(&BackupList {
+            zeebe: vec![
+                BackupDescriptor {
+                    backup_id: 1683214620,
+                    state: BackupState::Completed,
+                    details: vec![],
+                },
+                BackupDescriptor {
+                    backup_id: 200,
+                    state: BackupState::Failed,
+                    details: vec![],
+                },
+            ],
+            ..Default::default()
+        });
+        metrics.record_created(&CreatedBackup {
+            backup_id: 300,
+            zeebe_records_snapshot: None,
+            duration: Duration::from_millis(1500),
+            exporting_paused: Some(Duration::from_secs(2)),
+        });
+        metrics.record_failure("create");
+
+        let rendered = metrics.render();
+        for line in [
+            "c8_backup_last_success_timestamp_seconds{component=\"zeebe\"} 1683214620",
+            "c8_backup_backups{component=\"zeebe\",state=\"COMPLETED\"} 1",
+            "c8_backup_backups{component=\"zeebe\",state=\"FAILED\"} 1",
+            "c8_backup_duration_seconds 1.5",
+            "c8_backup_exporting_paused_seconds 2",
+            "c8_backup_failures_total{operation=\"create\"} 1",
+            "c8_backup_failures_total{operation=\"prune\"} 0",
+        ] {
+            assert!(rendered.lines().any(|l| l == line), "missing {}", line);
+        }
+    }
+}
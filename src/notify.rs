@@ -0,0 +1,342 @@
+use std::{
+    future::Future,
+    time::{Duration, Instant},
+};
+
+use bytes::Bytes;
+use chrono::Utc;
+use http_body_util::Full;
+use hyper::{header::CONTENT_TYPE, Method, Request, Uri};
+use serde_json::{json, Value};
+use tokio::time::timeout;
+use tracing::{debug, warn};
+
+use crate::{
+    error::Error,
+    external::ExternalEndpoint,
+    types::{CreatedBackup, DeletedBackup, NotifyArgs, RestoreTarget, RestoredBackup, StorageMode},
+};
+
+/// Notifications must not hold up or fail the operation they report on.
+const SEND_TIMEOUT: Duration = Duration::from_secs(10);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Format {
+    Json,
+    Slack,
+    Teams,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Event {
+    Started,
+    Succeeded,
+    Failed,
+}
+
+impl Event {
+    fn as_str(self) -> &'static str {
+        match self {
+            Event::Started => "started",
+            Event::Succeeded => "succeeded",
+            Event::Failed => "failed",
+        }
+    }
+}
+
+/// Result of an operation that notifications are sent for.
+pub(crate) trait Outcome {
+    /// Backups the operation affected.
+    fn backup_ids(&self) -> Vec<u64>;
+}
+
+impl Outcome for CreatedBackup {
+    fn backup_ids(&self) -> Vec<u64> {
+        vec![self.backup_id]
+    }
+}
+
+impl Outcome for RestoredBackup {
+    fn backup_ids(&self) -> Vec<u64> {
+        match self.target {
+            RestoreTarget::EsBackup { id, .. } | RestoreTarget::RdbmsBackupId { id } => vec![id],
+            RestoreTarget::RdbmsAuto | RestoreTarget::RdbmsPointInTime { .. } => vec![],
+        }
+    }
+}
+
+impl Outcome for DeletedBackup {
+    fn backup_ids(&self) -> Vec<u64> {
+        vec![self.backup_id]
+    }
+}
+
+/// IDs of pruned backups.
+impl Outcome for Vec<u64> {
+    fn backup_ids(&self) -> Vec<u64> {
+        self.clone()
+    }
+}
+
+struct Notification<'a> {
+    operation: &'static str,
+    event: Event,
+    namespace: &'a str,
+    components: &'static [&'static str],
+    backup_ids: Vec<u64>,
+    duration: Option<Duration>,
+    errors: Vec<String>,
+}
+
+impl Notification<'_> {
+    fn payload(&self, format: Format) -> Value {
+        match format {
+            Format::Json => json!({
+                "operation": self.operation,
+                "event": self.event.as_str(),
+                "namespace": self.namespace,
+                "components": self.components,
+                "backupIds": self.backup_ids,
+                "durationSeconds": self.duration.map(|d| d.as_secs_f64()),
+                "errors": self.errors,
+                "timestamp": Utc::now().to_rfc3339(),
+            }),
+            Format::Slack => json!({ "text": self.text() }),
+            Format::Teams => json!({
+                "@type": "MessageCard",
+                "@context": "https://schema.org/extensions",
+                "summary": self.title(),
+                "themeColor": match self.event {
+                    Event::Started => "0078D7",
+                    Event::Succeeded => "2EB886",
+                    Event::Failed => "D00000",
+                },
+                "title": self.title(),
+                "text": self.details(),
+            }),
+        }
+    }
+
+    fn title(&self) -> String {
+        let backups = match self.backup_ids.as_slice() {
+            [] => String::new(),
+            [id] => format!(" of backup {}", id),
+            ids => format!(
+                " of backups {}",
+                ids.iter()
+                    .map(u64::to_string)
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ),
+        };
+        format!(
+            "c8-backup {}{} {} in namespace {}",
+            self.operation,
+            backups,
+            self.event.as_str(),
+            self.namespace
+        )
+    }
+
+    fn details(&self) -> String {
+        let mut details = format!("Components: {}", self.components.join(", "));
+        if let Some(duration) = self.duration {
+            details.push_str(&format!(
+                "\nDuration: {}",
+                humantime::format_duration(Duration::from_secs(duration.as_secs()))
+            ));
+        }
+        if !self.errors.is_empty() {
+            details.push_str(&format!("\nError: {}", self.errors.join(": ")));
+        }
+        details
+    }
+
+    fn text(&self) -> String {
+        format!("{}\n{}", self.title(), self.details())
+    }
+}
+
+/// Runs an operation and notifies all configured webhooks when it starts, succeeds or fails.
+pub(crate) async fn notified<T: Outcome>(
+    args: &NotifyArgs,
+    namespace: &str,
+    storage_mode: StorageMode,
+    operation: &'static str,
+    backup_id: Option<u64>,
+    run: impl Future<Output = Result<T, Error>>,
+) -> Result<T, Error> {
+    let mut notification = Notification {
+        operation,
+        event: Event::Started,
+        namespace,
+        components: components(storage_mode),
+        backup_ids: backup_id.into_iter().collect(),
+        duration: None,
+        errors: vec![],
+    };
+    send(args, &notification).await;
+
+    let started = Instant::now();
+    let result = run.await;
+    notification.duration = Some(started.elapsed());
+    match &result {
+        Ok(outcome) => {
+            notification.event = Event::Succeeded;
+            notification.backup_ids = outcome.backup_ids();
+        }
+        Err(e) => {
+            notification.event = Event::Failed;
+            notification.errors = error_chain(e);
+        }
+    }
+    send(args, &notification).await;
+    result
+}
+
+fn components(storage_mode: StorageMode) -> &'static [&'static str] {
+    match storage_mode {
+        StorageMode::Elasticsearch => &["operate", "zeebe", "elasticsearch"],
+        StorageMode::Opensearch => &["operate", "zeebe", "opensearch"],
+        StorageMode::Rdbms => &["zeebe"],
+    }
+}
+
+/// Messages of the error and its sources, skipping sources that repeat the previous message.
+fn error_chain(e: &Error) -> Vec<String> {
+    let mut chain = vec![e.to_string()];
+    let mut source = std::error::Error::source(e);
+    while let Some(e) = source {
+        let message = e.to_string();
+        if chain.last() != Some(&message) {
+            chain.push(message);
+        }
+        source = e.source();
+    }
+    chain
+}
+
+async fn send(args: &NotifyArgs, notification: &Notification<'_>) {
+    let webhooks = args
+        .webhooks
+        .iter()
+        .map(|url| (Format::Json, url))
+        .chain(args.slack_webhooks.iter().map(|url| (Format::Slack, url)))
+        .chain(args.teams_webhooks.iter().map(|url| (Format::Teams, url)));
+    for (format, url) in webhooks {
+        let payload = notification.payload(format);
+        match timeout(SEND_TIMEOUT, post(url, &payload)).await {
+            Ok(Ok(())) => debug!("Notified {:?} webhook", format),
+            Ok(Err(e)) => warn!(error = %e, "Could not notify {:?} webhook", format),
+            Err(e) => warn!(error = %e, "Could not notify {:?} webhook", format),
+        }
+    }
+}
+
+async fn post(url: &str, payload: &Value) -> Result<(), Error> {
+    let uri: Uri = url.parse()?;
+    let origin = format!(
+        "{}://{}",
+        uri.scheme_str().unwrap_or_default(),
+        uri.authority().map(|a| a.as_str()).unwrap_or_default()
+    );
+    let path = uri.path_and_query().map(|p| p.as_str()).unwrap_or("/");
+    let endpoint = ExternalEndpoint::unauthenticated(&origin)?;
+    let req = Request::builder()
+        .method(Method::POST)
+        .uri(path)
+        .header(CONTENT_TYPE, "application/json")
+        .body(Full::new(Bytes::from(serde_json::to_vec(payload)?)))?;
+    endpoint.request(req).await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn notification(event: Event) -> Notification<'static> {
+        Notification {
+            operation: "create",
+            event,
+            namespace: "camunda",
+            components: components(StorageMode::Elasticsearch),
+            backup_ids: vec![1683214620],
+            duration: Some(Duration::from_millis(65_500)),
+            errors: if event == Event::Failed {
+                vec!["Backup failed: snapshot is PARTIAL".to_string()]
+            } else {
+                vec![]
+            },
+        }
+    }
+
+    #[test]
+    fn test_json_payload() {
+        let payload = notification(Event::Failed).payload(Format::Json);
+        assert_eq!(payload["operation"], "create");
+        assert_eq!(payload["event"], "failed");
+        assert_eq!(payload["backupIds"], json!([1683214620]));
+        assert_eq!(payload["durationSeconds"], 65.5);
+        assert_eq!(
+            payload["errors"],
+            json!(["Backup failed: snapshot is PARTIAL"])
+        );
+    }
+
+    #[test]
+    fn test_slack_payload() {
+        let payload = notification(Event::Succeeded).payload(Format::Slack);
+        assert_eq!(
+            payload,
+            json!({
+                "text": "c8-backup create of backup 1683214620 succeeded in namespace camunda\n\
+                         Components: operate, zeebe, elasticsearch\nDuration: 1m 5s"
+            })
+        );
+    }
+
+    #[test]
+    fn test_teams_payload() {
+        let payload = notification(Event::Failed).payload(Format::Teams);
+        assert_eq!(payload["@type"], "MessageCard");
+        assert_eq!(
+            payload["title"],
+            "c8-backup create of backup 1683214620 failed in namespace camunda"
+        );
+        assert!(payload["text"]
+            .as_str()
+            .unwrap()
+            .ends_with("Error: Backup failed: snapshot is PARTIAL"));
+    }
+
+    #[derive(Debug)]
+    struct Chained(&'static str, Option<Box<Chained>>);
+
+    impl std::fmt::Display for Chained {
+        fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+            write!(f, "{}", self.0)
+        }
+    }
+
+    impl std::error::Error for Chained {
+        fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+            self.1.as_deref().map(|e| e as _)
+        }
+    }
+
+    #[test]
+    fn test_error_chain_skips_repeated_messages() {
+        let e = Error::Other(Box::new(Chained(
+            "restore failed",
+            Some(Box::new(Chained(
+                "disk full",
+                Some(Box::new(Chained("disk full", None))),
+            ))),
+        )));
+        assert_eq!(
+            error_chain(&e),
+            vec!["restore failed".to_string(), "disk full".to_string()]
+        );
+    }
+}
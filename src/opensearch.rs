@@ -0,0 +1,214 @@
+use std::{collections::BTreeMap, time::Duration};
+
+use bytes::Bytes;
+use http_body_util::Full;
+use hyper::{header::CONTENT_TYPE, Request};
+use serde_json::{json, Value};
+
+use crate::{
+    common::Cluster,
+    elasticsearch::{
+        Connection, SearchClient, SnapshotRepository, SnapshotRequest, STANDARD_REPOSITORY_TYPES,
+    },
+    error::Error,
+    types::SearchArgs,
+};
+
+/// Prefix of the repositories that AWS-managed OpenSearch domains use for automated snapshots.
+/// They are read-only for everyone but the service itself.
+const AUTOMATED_REPOSITORY_PREFIX: &str = "cs-automated";
+
+const ISM_POLICIES_PATH: &str = "/_plugins/_ism/policies";
+
+/// The ISM API lists 20 policies unless asked for more.
+const MAX_ISM_POLICIES: usize = 10000;
+
+pub struct Opensearch {
+    connection: Connection,
+    repository: Option<String>,
+    poll_interval: Duration,
+}
+
+impl Opensearch {
+    pub async fn new(cluster: &Cluster, args: &SearchArgs) -> Result<Self, Error> {
+        Ok(Self {
+            connection: Connection::new(cluster, args, &cluster.settings.opensearch_selector)
+                .await?,
+            repository: args.snapshot_repository.clone(),
+            poll_interval: cluster.settings.poll_interval,
+        })
+    }
+}
+
+impl SearchClient for Opensearch {
+    async fn request(&self, req: Request<Full<Bytes>>) -> Result<Bytes, Error> {
+        self.connection.request(req).await
+    }
+
+    fn snapshot_request(&self, indices: &str) -> SnapshotRequest {
+        snapshot_request(indices)
+    }
+
+    fn is_usable_repository(&self, name: &str, repository: &SnapshotRepository) -> bool {
+        is_usable_repository(name, repository)
+    }
+
+    fn repository(&self) -> Option<&str> {
+        self.repository.as_deref()
+    }
+
+    fn poll_interval(&self) -> Duration {
+        self.poll_interval
+    }
+
+    /// `GET /*` also returns hidden plugin indices on OpenSearch, so only open indices are listed
+    /// through the cat API instead.
+    async fn list_indices(&self) -> Result<Vec<String>, Error> {
+        #[derive(serde::Deserialize, Debug)]
+        struct Index {
+            index: String,
+        }
+
+        let req = Request::builder()
+            .uri("/_cat/indices?format=json&h=index&expand_wildcards=open")
+            .method("GET")
+            .body(Full::default())
+            .expect("Request must be valid");
+        let indices: Vec<Index> = serde_json::from_slice(&self.request(req).await?)?;
+        Ok(indices.into_iter().map(|i| i.index).collect())
+    }
+
+    /// OpenSearch manages index lifecycles with ISM instead of ILM.
+    fn lifecycle_policy_settings(&self) -> &'static [&'static str] {
+        &[
+            "index.plugins.index_state_management.policy_id",
+            "index.opendistro.index_state_management.policy_id",
+        ]
+    }
+
+    async fn get_lifecycle_policies(&self) -> Result<BTreeMap<String, Value>, Error> {
+        #[derive(serde::Deserialize, Debug)]
+        struct Policies {
+            policies: Vec<Policy>,
+        }
+        #[derive(serde::Deserialize, Debug)]
+        struct Policy {
+            #[serde(rename = "_id")]
+            id: String,
+            policy: Value,
+        }
+
+        let req = Request::builder()
+            .uri(format!("{}?size={}", ISM_POLICIES_PATH, MAX_ISM_POLICIES))
+            .method("GET")
+            .body(Full::default())
+            .expect("Request must be valid");
+        let resp: Policies = serde_json::from_slice(&self.request(req).await?)?;
+        Ok(resp
+            .policies
+            .into_iter()
+            .map(|p| (p.id, creatable_ism_policy(p.policy)))
+            .collect())
+    }
+
+    async fn put_lifecycle_policy(&self, name: &str, policy: &Value) -> Result<(), Error> {
+        let req = Request::builder()
+            .uri(format!("{}/{}", ISM_POLICIES_PATH, name))
+            .method("PUT")
+            .header(CONTENT_TYPE, "application/json")
+            .body(Full::from(json!({ "policy": policy }).to_string()))
+            .expect("Request must be valid");
+        self.request(req).await?;
+        Ok(())
+    }
+}
+
+/// Removes the fields of an ISM policy that OpenSearch maintains itself and rejects on create.
+fn creatable_ism_policy(mut policy: Value) -> Value {
+    if let Some(policy) = policy.as_object_mut() {
+        policy.remove("policy_id");
+        policy.remove("last_updated_time");
+    }
+    match policy.get_mut("ism_template") {
+        Some(Value::Array(templates)) => templates.iter_mut().for_each(|t| {
+            t.as_object_mut().map(|t| t.remove("last_updated_time"));
+        }),
+        Some(Value::Object(template)) => {
+            template.remove("last_updated_time");
+        }
+        _ => {}
+    }
+    policy
+}
+
+/// OpenSearch has no feature states and rejects the field.
+fn snapshot_request(indices: &str) -> SnapshotRequest {
+    SnapshotRequest {
+        indices: indices.to_string(),
+        feature_states: None,
+        metadata: None,
+    }
+}
+
+fn is_usable_repository(name: &str, repository: &SnapshotRepository) -> bool {
+    !name.starts_with(AUTOMATED_REPOSITORY_PREFIX)
+        && STANDARD_REPOSITORY_TYPES.contains(&repository.r#type.as_str())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_is_usable_repository() {
+        let s3 = SnapshotRepository {
+            r#type: "s3".into(),
+            settings: Default::default(),
+        };
+        assert!(is_usable_repository("camunda", &s3));
+        assert!(!is_usable_repository("cs-automated-enc", &s3));
+        assert!(!is_usable_repository(
+            "camunda",
+            &SnapshotRepository {
+                r#type: "source".into(),
+                settings: Default::default(),
+            }
+        ));
+    }
+
+    #[test]
+    fn test_creatable_ism_policy() {
+        let policy = json!({
+            "policy_id": "zeebe-record-retention-policy",
+            "description": "Zeebe record retention policy",
+            "last_updated_time": 1683214620000u64,
+            "schema_version": 17,
+            "default_state": "initial",
+            "states": [],
+            "ism_template": [{
+                "index_patterns": ["zeebe-record_*"],
+                "priority": 0,
+                "last_updated_time": 1683214620000u64
+            }]
+        });
+        assert_eq!(
+            creatable_ism_policy(policy),
+            json!({
+                "description": "Zeebe record retention policy",
+                "schema_version": 17,
+                "default_state": "initial",
+                "states": [],
+                "ism_template": [{"index_patterns": ["zeebe-record_*"], "priority": 0}]
+            })
+        );
+    }
+
+    #[test]
+    fn test_snapshot_request_omits_feature_states() {
+        let req = snapshot_request("zeebe-record*");
+        assert_eq!(
+            serde_json::to_string(&req).unwrap(),
+            r#"{"indices":"zeebe-record*"}"#
+        );
+    }
+}
@@ -1,58 +1,56 @@
-use std::error::Error;
-
 use bytes::Bytes;
 use http_body_util::Full;
 use hyper::{header::CONTENT_TYPE, Request};
 
 use crate::{
-    common::make_component_request,
-    types::{BackupDescriptor, HistoryBackupInfo, OperateDetails, TakeBackupRequest},
+    common::{make_component_request, Cluster},
+    error::Error,
+    types::{BackupDescriptor, OperateDetails, TakeBackupRequest},
 };
 
 async fn make_operate_request(
-    kube: &kube::Client,
-    req: Request<Full<Bytes>>,
-) -> Result<Bytes, Box<dyn std::error::Error>> {
-    make_component_request(kube, "app.kubernetes.io/component=operate", 8080, req).await
-}
-
-#[allow(dead_code)]
-async fn make_management_request(
-    kube: &kube::Client,
+    cluster: &Cluster,
     req: Request<Full<Bytes>>,
-) -> Result<Bytes, Box<dyn std::error::Error>> {
-    make_component_request(kube, "app.kubernetes.io/component=zeebe-gateway", 9600, req).await
+) -> Result<Bytes, Error> {
+    let settings = &cluster.settings;
+    make_component_request(
+        cluster,
+        &settings.operate_selector,
+        settings.operate_port,
+        req,
+    )
+    .await
 }
 
-#[tracing::instrument(skip(kube), err, level = "debug")]
+#[tracing::instrument(skip(cluster), err, level = "debug")]
 pub(crate) async fn list_backups(
-    kube: &kube::Client,
-) -> Result<Vec<BackupDescriptor<OperateDetails>>, Box<dyn Error>> {
+    cluster: &Cluster,
+) -> Result<Vec<BackupDescriptor<OperateDetails>>, Error> {
     let req = Request::builder()
         .method("GET")
-        .uri(format!("/actuator/backups"))
+        .uri("/actuator/backups")
         .body(Full::default())?;
 
-    let resp = make_operate_request(kube, req).await?;
+    let resp = make_operate_request(cluster, req).await?;
     Ok(serde_json::from_slice(&resp)?)
 }
 
-#[tracing::instrument(skip(kube), err, level = "debug")]
+#[tracing::instrument(skip(cluster), err, level = "debug")]
 pub async fn query_backup(
-    kube: &kube::Client,
+    cluster: &Cluster,
     backup_id: u64,
-) -> Result<BackupDescriptor<OperateDetails>, Box<dyn Error>> {
+) -> Result<BackupDescriptor<OperateDetails>, Error> {
     let req = Request::builder()
         .method("GET")
         .uri(format!("/actuator/backups/{}", backup_id))
         .body(Full::default())?;
 
-    let resp = make_operate_request(kube, req).await?;
+    let resp = make_operate_request(cluster, req).await?;
     Ok(serde_json::from_slice(&resp)?)
 }
 
-#[tracing::instrument(skip(kube), err)]
-pub async fn take_backup(kube: &kube::Client, backup_id: u64) -> Result<(), Box<dyn Error>> {
+#[tracing::instrument(skip(cluster), err)]
+pub async fn take_backup(cluster: &Cluster, backup_id: u64) -> Result<(), Error> {
     let req = Request::builder()
         .method("POST")
         .uri("/actuator/backups")
@@ -64,52 +62,17 @@ pub async fn take_backup(kube: &kube::Client, backup_id: u64) -> Result<(), Box<
             .expect("Backup must be serializable"),
         ))?;
 
-    make_operate_request(kube, req).await?;
+    make_operate_request(cluster, req).await?;
     Ok(())
 }
 
-// --- RDBMS History Backup API ---
-
-#[tracing::instrument(skip(kube), err)]
-pub async fn take_history_backup(
-    kube: &kube::Client,
-    backup_id: u64,
-) -> Result<(), Box<dyn Error>> {
+#[tracing::instrument(skip(cluster), err)]
+pub async fn delete_backup(cluster: &Cluster, backup_id: u64) -> Result<(), Error> {
     let req = Request::builder()
-        .method("POST")
-        .uri("/actuator/backupHistory")
-        .header(CONTENT_TYPE, "application/json")
-        .body(Full::from(
-            serde_json::to_string(&TakeBackupRequest {
-                backup_id: backup_id.to_string(),
-            })
-            .expect("Backup must be serializable"),
-        ))?;
-    make_management_request(kube, req).await?;
-    Ok(())
-}
-
-#[tracing::instrument(skip(kube), err, level = "debug")]
-pub async fn query_history_backup(
-    kube: &kube::Client,
-    backup_id: u64,
-) -> Result<HistoryBackupInfo, Box<dyn Error>> {
-    let req = Request::builder()
-        .method("GET")
-        .uri(format!("/actuator/backupHistory/{}", backup_id))
+        .method("DELETE")
+        .uri(format!("/actuator/backups/{}", backup_id))
         .body(Full::default())?;
-    let resp = make_management_request(kube, req).await?;
-    Ok(serde_json::from_slice(&resp)?)
-}
 
-#[tracing::instrument(skip(kube), err, level = "debug")]
-pub async fn list_history_backups(
-    kube: &kube::Client,
-) -> Result<Vec<HistoryBackupInfo>, Box<dyn Error>> {
-    let req = Request::builder()
-        .method("GET")
-        .uri("/actuator/backupHistory")
-        .body(Full::default())?;
-    let resp = make_management_request(kube, req).await?;
-    Ok(serde_json::from_slice(&resp)?)
+    make_operate_request(cluster, req).await?;
+    Ok(())
 }
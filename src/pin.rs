@@ -0,0 +1,98 @@
+use std::time::Duration;
+
+use tracing::{info, warn};
+
+use crate::{
+    catalog,
+    common::Cluster,
+    delete::pinned_in_snapshot,
+    elasticsearch::Elasticsearch,
+    error::Error,
+    opensearch::Opensearch,
+    prefixes::IndexPrefixes,
+    types::{CatalogEntry, IndexPrefixArgs, SearchArgs, StorageMode},
+};
+
+/// Records in the catalog whether the backup is pinned. Backups without a catalog entry, such as
+/// those created before the catalog existed, get one collected from the components.
+///
+/// Only `create --pin` also records the pin in the snapshot metadata, which is immutable. The
+/// catalog entry takes precedence over it, so a pin set or removed here only lasts as long as
+/// the catalog entry.
+#[tracing::instrument(skip(cluster, search_args), err)]
+pub(crate) async fn set_pinned(
+    cluster: &Cluster,
+    storage_mode: StorageMode,
+    search_args: &SearchArgs,
+    index_prefixes: &IndexPrefixArgs,
+    catalog: &str,
+    backup_id: u64,
+    pinned: bool,
+) -> Result<CatalogEntry, Error> {
+    let entry = match catalog::read(cluster, catalog)
+        .await?
+        .into_iter()
+        .find(|e| e.backup_id == backup_id)
+    {
+        Some(entry) => entry,
+        None => {
+            catalog::describe_backup(cluster, storage_mode, backup_id, None, Duration::ZERO).await?
+        }
+    };
+    let entry = CatalogEntry { pinned, ..entry };
+    catalog::write(cluster, catalog, &entry).await?;
+    if pinned {
+        info!("Pinned backup {}", backup_id);
+        warn!(
+            "The pin of backup {} is only recorded in catalog {} and is lost if the catalog is \
+             deleted, snapshot metadata cannot be changed after a backup is created",
+            backup_id, catalog
+        );
+    } else {
+        info!("Unpinned backup {}", backup_id);
+        match pinned_at_creation(cluster, storage_mode, search_args, index_prefixes, &entry).await {
+            Ok(false) => {}
+            Ok(true) => warn!(
+                "Backup {} was created with --pin, which its snapshot metadata keeps recording. \
+                 It is pinned again if its entry in catalog {} is lost",
+                backup_id, catalog
+            ),
+            Err(e) => {
+                warn!(error = %e, "Could not check whether backup {} was created with --pin", backup_id)
+            }
+        }
+    }
+    Ok(entry)
+}
+
+/// Whether the backup was created with `--pin`, which is only recorded in snapshot metadata by
+/// Elasticsearch and OpenSearch backups.
+async fn pinned_at_creation(
+    cluster: &Cluster,
+    storage_mode: StorageMode,
+    search_args: &SearchArgs,
+    index_prefixes: &IndexPrefixArgs,
+    entry: &CatalogEntry,
+) -> Result<bool, Error> {
+    let prefixes = match storage_mode {
+        StorageMode::Elasticsearch | StorageMode::Opensearch => {
+            IndexPrefixes::discover(cluster, index_prefixes).await?
+        }
+        StorageMode::Rdbms => return Ok(false),
+    };
+    let name = entry
+        .zeebe_records_snapshot
+        .clone()
+        .unwrap_or_else(|| prefixes.zeebe_records_snapshot(entry.backup_id));
+    match storage_mode {
+        StorageMode::Elasticsearch => {
+            let search = Elasticsearch::new(cluster, search_args).await?;
+            pinned_in_snapshot(&search, &name).await
+        }
+        StorageMode::Opensearch => {
+            let search = Opensearch::new(cluster, search_args).await?;
+            pinned_in_snapshot(&search, &name).await
+        }
+        StorageMode::Rdbms => Ok(false),
+    }
+}
@@ -0,0 +1,219 @@
+use k8s_openapi::api::{
+    apps::v1::{Deployment, StatefulSet},
+    core::v1::{Container, PodTemplateSpec},
+};
+use kube::{api::ListParams, Api};
+use tracing::{debug, info};
+
+use crate::common::Cluster;
+use crate::error::Error;
+use crate::types::IndexPrefixArgs;
+
+const ZEEBE_PREFIX_ENV: &[&str] = &[
+    "ZEEBE_BROKER_EXPORTERS_ELASTICSEARCH_ARGS_INDEX_PREFIX",
+    "ZEEBE_BROKER_EXPORTERS_OPENSEARCH_ARGS_INDEX_PREFIX",
+];
+const OPERATE_PREFIX_ENV: &[&str] = &[
+    "CAMUNDA_OPERATE_ELASTICSEARCH_INDEXPREFIX",
+    "CAMUNDA_OPERATE_OPENSEARCH_INDEXPREFIX",
+];
+const TASKLIST_PREFIX_ENV: &[&str] = &[
+    "CAMUNDA_TASKLIST_ELASTICSEARCH_INDEXPREFIX",
+    "CAMUNDA_TASKLIST_OPENSEARCH_INDEXPREFIX",
+];
+
+/// Index prefixes used by the Camunda components of a deployment.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IndexPrefixes {
+    pub zeebe: String,
+    pub operate: String,
+    pub tasklist: String,
+    pub optimize: String,
+}
+
+impl Default for IndexPrefixes {
+    fn default() -> Self {
+        Self {
+            zeebe: "zeebe-record".into(),
+            operate: "operate".into(),
+            tasklist: "tasklist".into(),
+            optimize: "optimize".into(),
+        }
+    }
+}
+
+impl IndexPrefixes {
+    /// Resolves the prefixes, preferring explicit flags over the environment of the deployed
+    /// components and falling back to the Helm chart defaults.
+    #[tracing::instrument(skip(cluster), err, level = "debug")]
+    pub async fn discover(cluster: &Cluster, args: &IndexPrefixArgs) -> Result<Self, Error> {
+        let defaults = Self::default();
+        let settings = &cluster.settings;
+        let zeebe = match &args.zeebe_index_prefix {
+            Some(prefix) => prefix.clone(),
+            None => statefulset_env(cluster, &settings.zeebe_broker_selector, ZEEBE_PREFIX_ENV)
+                .await?
+                .unwrap_or(defaults.zeebe),
+        };
+        let operate = match &args.operate_index_prefix {
+            Some(prefix) => prefix.clone(),
+            None => deployment_env(cluster, &settings.operate_selector, OPERATE_PREFIX_ENV)
+                .await?
+                .unwrap_or(defaults.operate),
+        };
+        let tasklist = match &args.tasklist_index_prefix {
+            Some(prefix) => prefix.clone(),
+            None => deployment_env(cluster, &settings.tasklist_selector, TASKLIST_PREFIX_ENV)
+                .await?
+                .unwrap_or(defaults.tasklist),
+        };
+        let optimize = args
+            .optimize_index_prefix
+            .clone()
+            .unwrap_or(defaults.optimize);
+
+        let prefixes = Self {
+            zeebe,
+            operate,
+            tasklist,
+            optimize,
+        };
+        info!("Using index prefixes {:?}", prefixes);
+        Ok(prefixes)
+    }
+
+    /// Index pattern matching all records exported by Zeebe.
+    pub fn zeebe_records_pattern(&self) -> String {
+        format!("{}_*", self.zeebe)
+    }
+
+    /// Prefixes of all indices owned by Camunda components, including the delimiter so that
+    /// unrelated indices sharing the leading characters are not matched. The Zeebe exporter names
+    /// its indices `<prefix>_<valueType>_…`, the other components `<prefix>-…`.
+    pub fn owned_prefixes(&self) -> Vec<String> {
+        vec![
+            format!("{}_", self.zeebe),
+            format!("{}-", self.operate),
+            format!("{}-", self.tasklist),
+            format!("{}-", self.optimize),
+        ]
+    }
+
+    /// Name of the snapshot holding the exported Zeebe records of a backup. Installations with a
+    /// custom prefix get the prefix in the name so they can share a snapshot repository.
+    pub fn zeebe_records_snapshot(&self, backup_id: u64) -> String {
+        if self.zeebe == Self::default().zeebe {
+            format!("camunda_zeebe_records_{}", backup_id)
+        } else {
+            format!("camunda_zeebe_records_{}_{}", self.zeebe, backup_id)
+        }
+    }
+
+    /// Name of the index holding the templates and lifecycle policies of Camunda indices. It is
+    /// included in the Zeebe records snapshot.
+    pub fn templates_index(&self) -> String {
+        if self.zeebe == Self::default().zeebe {
+            "c8-backup-templates".to_string()
+        } else {
+            format!("c8-backup-templates-{}", self.zeebe)
+        }
+    }
+}
+
+async fn statefulset_env(
+    cluster: &Cluster,
+    labels: &str,
+    names: &[&str],
+) -> Result<Option<String>, Error> {
+    let sfs: Api<StatefulSet> = Api::default_namespaced(cluster.kube.clone());
+    let statefulsets = sfs.list(&ListParams::default().labels(labels)).await?;
+    let value = statefulsets
+        .iter()
+        .filter_map(|s| s.spec.as_ref())
+        .find_map(|spec| template_env(&spec.template, names));
+    Ok(value)
+}
+
+async fn deployment_env(
+    cluster: &Cluster,
+    labels: &str,
+    names: &[&str],
+) -> Result<Option<String>, Error> {
+    let deploy: Api<Deployment> = Api::default_namespaced(cluster.kube.clone());
+    let deployments = deploy.list(&ListParams::default().labels(labels)).await?;
+    let value = deployments
+        .iter()
+        .filter_map(|d| d.spec.as_ref())
+        .find_map(|spec| template_env(&spec.template, names));
+    Ok(value)
+}
+
+/// Returns the value of the first of the given environment variables set in the template.
+fn template_env(template: &PodTemplateSpec, names: &[&str]) -> Option<String> {
+    let containers: &[Container] = template.spec.as_ref().map_or(&[], |s| &s.containers);
+    let env = containers
+        .iter()
+        .flat_map(|c| c.env.iter().flatten())
+        .find(|env| names.contains(&env.name.as_str()) && env.value.is_some())?;
+    debug!("Found {}={:?}", env.name, env.value);
+    env.value.clone().filter(|value| !value.is_empty())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use k8s_openapi::api::core::v1::{EnvVar, PodSpec};
+
+    #[test]
+    fn test_default_prefixes() {
+        let prefixes = IndexPrefixes::default();
+        assert_eq!(prefixes.zeebe_records_pattern(), "zeebe-record_*");
+        assert_eq!(
+            prefixes.zeebe_records_snapshot(1683214620),
+            "camunda_zeebe_records_1683214620"
+        );
+        assert_eq!(prefixes.templates_index(), "c8-backup-templates");
+        assert_eq!(
+            prefixes.owned_prefixes(),
+            vec!["zeebe-record_", "operate-", "tasklist-", "optimize-"]
+        );
+    }
+
+    #[test]
+    fn test_custom_zeebe_prefix_changes_snapshot_name() {
+        let prefixes = IndexPrefixes {
+            zeebe: "prod-zeebe".into(),
+            ..Default::default()
+        };
+        assert_eq!(prefixes.zeebe_records_pattern(), "prod-zeebe_*");
+        assert_eq!(
+            prefixes.zeebe_records_snapshot(42),
+            "camunda_zeebe_records_prod-zeebe_42"
+        );
+        assert_eq!(prefixes.templates_index(), "c8-backup-templates-prod-zeebe");
+    }
+
+    #[test]
+    fn test_template_env() {
+        let template = PodTemplateSpec {
+            spec: Some(PodSpec {
+                containers: vec![Container {
+                    name: "operate".into(),
+                    env: Some(vec![EnvVar {
+                        name: "CAMUNDA_OPERATE_OPENSEARCH_INDEXPREFIX".into(),
+                        value: Some("custom-operate".into()),
+                        value_from: None,
+                    }]),
+                    ..Default::default()
+                }],
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+        assert_eq!(
+            template_env(&template, OPERATE_PREFIX_ENV).as_deref(),
+            Some("custom-operate")
+        );
+        assert_eq!(template_env(&template, TASKLIST_PREFIX_ENV), None);
+    }
+}
@@ -0,0 +1,239 @@
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+};
+
+use serde::Deserialize;
+
+use crate::{error::Error, types::ComponentSettings};
+
+/// Configuration file that is read if no other one is given.
+pub const DEFAULT_PATH: &str = "c8-backup.toml";
+
+/// Profile that is used if no other one is given and the configuration file defines it.
+pub const DEFAULT_PROFILE: &str = "default";
+
+#[derive(Deserialize, Debug, Default)]
+#[serde(deny_unknown_fields)]
+struct ConfigFile {
+    #[serde(default)]
+    profiles: BTreeMap<String, Profile>,
+}
+
+/// Settings of one deployment in the configuration file, in a `[profiles.<name>]` table.
+#[derive(Deserialize, Debug, Default, PartialEq)]
+pub struct Profile {
+    /// Where the components of the deployment are found, from the `components` table
+    #[serde(default)]
+    pub components: ComponentSettings,
+    /// Defaults of command line options, keyed by their long name, e.g. `storage-mode`. Options
+    /// of a subcommand are set in a table named after it, e.g. `[profiles.<name>.restore]`
+    #[serde(flatten)]
+    pub options: BTreeMap<String, toml::Value>,
+}
+
+impl Profile {
+    /// Values of the options outside of subcommand tables as they would be given on the command
+    /// line. Arrays set options that can be repeated, such as `webhook`.
+    pub fn option_values(&self) -> Result<BTreeMap<String, Vec<String>>, Error> {
+        values(
+            self.options.iter().filter(|(_, value)| !value.is_table()),
+            "",
+        )
+    }
+
+    /// Values of the options in the table of each subcommand, keyed by the name of the subcommand.
+    pub fn subcommand_option_values(
+        &self,
+    ) -> Result<BTreeMap<String, BTreeMap<String, Vec<String>>>, Error> {
+        self.options
+            .iter()
+            .filter_map(|(name, value)| Some((name, value.as_table()?)))
+            .map(|(name, table)| {
+                values(table.iter(), &format!("{}.", name)).map(|values| (name.clone(), values))
+            })
+            .collect()
+    }
+}
+
+/// Values of the `options`, whose names are prefixed with `scope` in errors.
+fn values<'a>(
+    options: impl Iterator<Item = (&'a String, &'a toml::Value)>,
+    scope: &str,
+) -> Result<BTreeMap<String, Vec<String>>, Error> {
+    options
+        .map(|(name, value)| {
+            let values = match value {
+                toml::Value::Array(values) => values.iter().map(scalar).collect(),
+                value => scalar(value).map(|v| vec![v]),
+            };
+            values
+                .map(|values| (name.clone(), values))
+                .map_err(|e| format!("Invalid value of option `{}{}`: {}", scope, name, e).into())
+        })
+        .collect()
+}
+
+fn scalar(value: &toml::Value) -> Result<String, String> {
+    match value {
+        toml::Value::String(s) => Ok(s.clone()),
+        toml::Value::Integer(i) => Ok(i.to_string()),
+        toml::Value::Float(f) => Ok(f.to_string()),
+        toml::Value::Boolean(b) => Ok(b.to_string()),
+        toml::Value::Datetime(d) => Ok(d.to_string()),
+        toml::Value::Array(_) | toml::Value::Table(_) => Err(format!(
+            "expected a string, number or boolean, found {}",
+            value
+        )),
+    }
+}
+
+/// Loads the profile `name` of the configuration file at `path`. Without a `path`,
+/// [`DEFAULT_PATH`] is read if it exists. Without a `name`, the [`DEFAULT_PROFILE`] is used if the
+/// file defines it, otherwise the profile is empty.
+pub fn load(path: Option<&Path>, name: Option<&str>) -> Result<Profile, Error> {
+    let (path, explicit) = match path {
+        Some(path) => (path.to_path_buf(), true),
+        None => (PathBuf::from(DEFAULT_PATH), false),
+    };
+    let contents = match std::fs::read_to_string(&path) {
+        Ok(contents) => contents,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound && !explicit && name.is_none() => {
+            return Ok(Profile::default())
+        }
+        Err(e) => return Err(format!("Could not read {}: {}", path.display(), e).into()),
+    };
+    parse(&contents, name).map_err(|e| format!("Invalid {}: {}", path.display(), e).into())
+}
+
+fn parse(contents: &str, name: Option<&str>) -> Result<Profile, Error> {
+    let mut file: ConfigFile = toml::from_str(contents)?;
+    match name {
+        Some(name) => file.profiles.remove(name).ok_or_else(|| {
+            let known = file.profiles.keys().cloned().collect::<Vec<_>>();
+            format!(
+                "unknown profile `{}`, defined profiles are: {}",
+                name,
+                known.join(", ")
+            )
+            .into()
+        }),
+        None => Ok(file.profiles.remove(DEFAULT_PROFILE).unwrap_or_default()),
+    }
+}
+
+/// Value of the long option `name` in the command line `args`, given as `--name value` or
+/// `--name=value`, before the command line is parsed.
+pub fn find_option(args: &[String], name: &str) -> Option<String> {
+    let flag = format!("--{}", name);
+    let mut args = args.iter().take_while(|arg| *arg != "--");
+    while let Some(arg) = args.next() {
+        if *arg == flag {
+            return args.next().cloned();
+        }
+        if let Some(value) = arg
+            .strip_prefix(&flag)
+            .and_then(|rest| rest.strip_prefix('='))
+        {
+            return Some(value.to_string());
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+
+    const FILE: &str = r#"
+        [profiles.default]
+        namespace = "camunda"
+
+        [profiles.prod]
+        namespace = "camunda-prod"
+        storage-mode = "opensearch"
+        retry-attempts = 10
+        webhook = ["https://hooks.example.com/a", "https://hooks.example.com/b"]
+
+        [profiles.prod.restore]
+        allow-yellow = true
+
+        [profiles.prod.components]
+        zeebe-gateway-selector = "app=gateway"
+        operate-port = 9090
+        poll-interval = "30s"
+    "#;
+
+    #[test]
+    fn test_parse_profile() {
+        let profile = parse(FILE, Some("prod")).unwrap();
+        assert_eq!(profile.components.zeebe_gateway_selector, "app=gateway");
+        assert_eq!(profile.components.operate_port, 9090);
+        assert_eq!(profile.components.poll_interval, Duration::from_secs(30));
+        assert_eq!(profile.components.zeebe_port, 9600);
+
+        let values = profile.option_values().unwrap();
+        assert_eq!(values["namespace"], vec!["camunda-prod"]);
+        assert_eq!(values["retry-attempts"], vec!["10"]);
+        assert_eq!(values["webhook"].len(), 2);
+        assert!(!values.contains_key("allow-yellow"));
+        assert!(!values.contains_key("restore"));
+
+        let subcommands = profile.subcommand_option_values().unwrap();
+        assert_eq!(subcommands.len(), 1);
+        assert_eq!(subcommands["restore"]["allow-yellow"], vec!["true"]);
+    }
+
+    #[test]
+    fn test_parse_default_profile() {
+        let profile = parse(FILE, None).unwrap();
+        assert_eq!(profile.components, ComponentSettings::default());
+        assert_eq!(
+            profile.option_values().unwrap()["namespace"],
+            vec!["camunda"]
+        );
+        assert_eq!(parse("", None).unwrap(), Profile::default());
+    }
+
+    #[test]
+    fn test_parse_invalid() {
+        let e = parse(FILE, Some("staging")).unwrap_err();
+        assert_eq!(
+            e.to_string(),
+            "unknown profile `staging`, defined profiles are: default, prod"
+        );
+        assert!(parse("[profiles.x.components]\nzeebe-port = \"x\"", Some("x")).is_err());
+        assert!(parse("[profiles.x.components]\nunknown = 1", Some("x")).is_err());
+        let nested = parse("[profiles.x.restore.search]\nurl = \"x\"", Some("x")).unwrap();
+        assert!(nested.option_values().unwrap().is_empty());
+        let e = nested.subcommand_option_values().unwrap_err();
+        assert_eq!(
+            e.to_string(),
+            "Invalid value of option `restore.search`: expected a string, number or boolean, \
+             found { url = \"x\" }"
+        );
+    }
+
+    #[test]
+    fn test_find_option() {
+        let args = |s: &str| s.split(' ').map(String::from).collect::<Vec<_>>();
+        assert_eq!(
+            find_option(&args("c8-backup list --profile prod -n camunda"), "profile").as_deref(),
+            Some("prod")
+        );
+        assert_eq!(
+            find_option(&args("c8-backup --profile=prod list"), "profile").as_deref(),
+            Some("prod")
+        );
+        assert_eq!(
+            find_option(&args("c8-backup --profiles x list"), "profile"),
+            None
+        );
+        assert_eq!(
+            find_option(&args("c8-backup list -- --profile x"), "profile"),
+            None
+        );
+    }
+}
@@ -1,5 +1,10 @@
-use std::collections::HashMap;
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    time::Duration,
+};
 
+use chrono::Utc;
+use futures::{stream, StreamExt, TryStreamExt};
 use k8s_openapi::api::{
     apps::v1::{Deployment, StatefulSet},
     batch::v1::{Job, JobSpec},
@@ -11,17 +16,31 @@ use k8s_openapi::api::{
 use kube::{
     api::{DeleteParams, ListParams, Patch, PatchParams, PostParams},
     core::ObjectMeta,
-    runtime::{conditions, wait::await_condition},
-    Api,
+    runtime::{conditions, events::EventType, wait::await_condition},
+    Api, ResourceExt,
 };
 
 use serde_json::json;
-use tracing::info;
+use tracing::{info, warn};
 
 use crate::{
-    elasticsearch::{delete_index, get_all_indices, restore_snapshot},
-    list, operate,
-    types::{RestoreTarget, StorageMode},
+    catalog,
+    common::Cluster,
+    elasticsearch::{
+        bulk_delete_indices, delete_data_streams, get_aliases, get_all_indices, get_data_streams,
+        get_snapshot_indices, put_index_settings, restore_snapshot, wait_for_health, Alias,
+        DataStream, Elasticsearch, SearchClient,
+    },
+    error::Error,
+    events, list,
+    opensearch::Opensearch,
+    operate,
+    prefixes::IndexPrefixes,
+    settings, templates,
+    types::{
+        ComponentSettings, IndexPrefixArgs, IndexRestoreArgs, LabelSelector, RestoreTarget,
+        RestoredBackup, SearchArgs, StorageMode,
+    },
     zeebe,
 };
 
@@ -31,27 +50,124 @@ struct Backup {
     snapshots: Vec<String>,
 }
 
-#[tracing::instrument(err)]
+#[tracing::instrument(skip(cluster, search_args), err)]
+#[allow(clippy::too_many_arguments)]
 pub(crate) async fn restore(
+    cluster: &Cluster,
     storage_mode: StorageMode,
     to: Option<String>,
     backup_id: Option<u64>,
-) -> Result<(), Box<dyn std::error::Error>> {
-    let kube = kube::Client::try_default().await?;
-
-    match storage_mode {
-        StorageMode::Elasticsearch => restore_es(&kube).await,
-        StorageMode::Rdbms => {
-            let target = determine_restore_target(to, backup_id)?;
-            restore_rdbms(&kube, &target).await
+    search_args: &SearchArgs,
+    index_prefixes: &IndexPrefixArgs,
+    index_restore: &IndexRestoreArgs,
+    catalog: &str,
+    label: Option<&LabelSelector>,
+) -> Result<RestoredBackup, Error> {
+    check_restore_args(storage_mode, to.as_deref(), backup_id, label)?;
+    let run_id = Utc::now().format("%Y%m%dT%H%M%SZ").to_string();
+    publish_restore_event(
+        cluster,
+        EventType::Normal,
+        "RestoreStarted",
+        format!("Restore {} started", run_id),
+    )
+    .await;
+    let result: Result<RestoredBackup, Error> = async {
+        match storage_mode {
+            StorageMode::Elasticsearch => {
+                let prefixes = IndexPrefixes::discover(cluster, index_prefixes).await?;
+                let search = Elasticsearch::new(cluster, search_args).await?;
+                restore_es(
+                    cluster,
+                    &search,
+                    &prefixes,
+                    index_restore,
+                    catalog,
+                    label,
+                    &run_id,
+                )
+                .await
+            }
+            StorageMode::Opensearch => {
+                let prefixes = IndexPrefixes::discover(cluster, index_prefixes).await?;
+                let search = Opensearch::new(cluster, search_args).await?;
+                restore_es(
+                    cluster,
+                    &search,
+                    &prefixes,
+                    index_restore,
+                    catalog,
+                    label,
+                    &run_id,
+                )
+                .await
+            }
+            StorageMode::Rdbms => {
+                let target = match label {
+                    Some(label) => find_newest_runtime_backup(cluster, catalog, label).await?,
+                    None => determine_restore_target(to, backup_id)?,
+                };
+                restore_rdbms(cluster, &target, &run_id).await?;
+                Ok(RestoredBackup {
+                    target,
+                    indices: vec![],
+                })
+            }
+        }
+    }
+    .await;
+    match &result {
+        Ok(_) => {
+            publish_restore_event(
+                cluster,
+                EventType::Normal,
+                "RestoreCompleted",
+                format!("Restore {} completed", run_id),
+            )
+            .await
+        }
+        Err(e) => {
+            publish_restore_event(
+                cluster,
+                EventType::Warning,
+                "RestoreFailed",
+                format!("Restore {} failed: {}", run_id, e),
+            )
+            .await
         }
     }
+    result
+}
+
+/// Publishes an event about the progress of a restore on the Zeebe brokers.
+async fn publish_restore_event(cluster: &Cluster, type_: EventType, reason: &str, note: String) {
+    let selector = &cluster.settings.zeebe_broker_selector;
+    for object in events::objects::<StatefulSet>(cluster, selector).await {
+        events::publish(cluster, &object, type_, "Restore", reason, note.clone()).await;
+    }
+}
+
+/// Rejects combinations of restore arguments that cannot be honored, before anything is
+/// announced. Elasticsearch and OpenSearch always restore the most recent usable backup.
+pub(crate) fn check_restore_args(
+    storage_mode: StorageMode,
+    to: Option<&str>,
+    backup_id: Option<u64>,
+    label: Option<&LabelSelector>,
+) -> Result<(), Error> {
+    if label.is_some() && (to.is_some() || backup_id.is_some()) {
+        return Err("Cannot specify --label together with --to or --backup-id".into());
+    }
+    if !matches!(storage_mode, StorageMode::Rdbms) && (to.is_some() || backup_id.is_some()) {
+        return Err("--to and --backup-id are only supported in RDBMS mode".into());
+    }
+    Ok(())
 }
 
 fn determine_restore_target(
     to: Option<String>,
     backup_id: Option<u64>,
-) -> Result<RestoreTarget, Box<dyn std::error::Error>> {
+) -> Result<RestoreTarget, Error> {
     match (to, backup_id) {
         (Some(_), Some(_)) => Err("Cannot specify both --to and --backup-id".into()),
         (Some(ts), None) => Ok(RestoreTarget::RdbmsPointInTime { to: ts }),
@@ -69,38 +185,98 @@ fn restore_args_for_target(target: &RestoreTarget) -> Vec<String> {
     }
 }
 
-#[tracing::instrument(skip(kube), err)]
-async fn restore_es(kube: &kube::Client) -> Result<(), Box<dyn std::error::Error>> {
-    let backup = find_newest_backup(kube).await?;
-    let restartable = shutdown_apps(kube).await?;
+#[tracing::instrument(skip(cluster, search, prefixes), err)]
+async fn restore_es(
+    cluster: &Cluster,
+    search: &impl SearchClient,
+    prefixes: &IndexPrefixes,
+    index_restore: &IndexRestoreArgs,
+    catalog: &str,
+    label: Option<&LabelSelector>,
+    run_id: &str,
+) -> Result<RestoredBackup, Error> {
+    let backup = find_newest_backup(cluster, prefixes, catalog, label).await?;
+    let restartable = shutdown_apps(cluster, run_id).await?;
 
-    delete_indices(kube).await?;
-    restore_indices(kube, &backup).await?;
+    let mut snapshot_indices = HashSet::new();
+    for snapshot in &backup.snapshots {
+        snapshot_indices.extend(get_snapshot_indices(search, snapshot).await?);
+    }
+    let mut index_prefixes = prefixes.owned_prefixes();
+    index_prefixes.extend(index_restore.extra_index_prefixes.iter().cloned());
+    delete_indices(search, &snapshot_indices, &index_prefixes).await?;
+    let index_settings: BTreeMap<String, String> =
+        index_restore.index_settings.iter().cloned().collect();
+    restore_indices(
+        search,
+        &backup,
+        index_restore.restore_parallelism.get(),
+        &index_settings,
+        index_restore.recovery_timeout,
+    )
+    .await?;
+    let mut restored = snapshot_indices.into_iter().collect::<Vec<_>>();
+    restored.sort();
+    if !index_settings.is_empty() {
+        reset_index_settings(search, prefixes, &restored, &index_settings).await?;
+    }
+    wait_for_health(
+        search,
+        &restored,
+        index_restore.allow_yellow,
+        index_restore.health_timeout,
+    )
+    .await?;
+    publish_restore_event(
+        cluster,
+        EventType::Normal,
+        "IndicesRestored",
+        format!(
+            "Restore {} restored {} indices from backup {}",
+            run_id,
+            restored.len(),
+            backup.id
+        ),
+    )
+    .await;
+    let templates_index = prefixes.templates_index();
+    if restored.contains(&templates_index) {
+        templates::restore_missing_templates(search, &templates_index).await?;
+    } else {
+        warn!("Backup contains no index templates, make sure they exist before Camunda starts");
+    }
 
-    delete_zeebe_data(kube).await?;
-    restore_zeebe_data_es(kube, &backup).await?;
+    delete_zeebe_data(cluster, run_id).await?;
+    restore_zeebe_data_es(cluster, &backup, run_id).await?;
 
-    start_apps(kube, &restartable).await?;
-    Ok(())
+    start_apps(cluster, &restartable, run_id).await?;
+    Ok(RestoredBackup {
+        target: RestoreTarget::EsBackup {
+            id: backup.id,
+            snapshots: backup.snapshots,
+        },
+        indices: restored,
+    })
 }
 
-#[tracing::instrument(skip(kube), err)]
+#[tracing::instrument(skip(cluster), err)]
 async fn restore_rdbms(
-    kube: &kube::Client,
+    cluster: &Cluster,
     target: &RestoreTarget,
-) -> Result<(), Box<dyn std::error::Error>> {
-    let restartable = shutdown_apps(kube).await?;
+    run_id: &str,
+) -> Result<(), Error> {
+    let restartable = shutdown_apps(cluster, run_id).await?;
 
     // No ES index operations in RDBMS mode
 
-    delete_zeebe_data(kube).await?;
-    restore_zeebe_data_rdbms(kube, target).await?;
+    delete_zeebe_data(cluster, run_id).await?;
+    restore_zeebe_data_rdbms(cluster, target, run_id).await?;
 
-    start_apps(kube, &restartable).await?;
+    start_apps(cluster, &restartable, run_id).await?;
     Ok(())
 }
 
-fn zeebe_data_deletion_job(pvc: &PersistentVolumeClaim) -> Job {
+fn zeebe_data_deletion_job(settings: &ComponentSettings, pvc: &PersistentVolumeClaim) -> Job {
     let name = pvc.metadata.name.to_owned().expect("PVC must have a name");
     Job {
         metadata: ObjectMeta {
@@ -112,15 +288,15 @@ fn zeebe_data_deletion_job(pvc: &PersistentVolumeClaim) -> Job {
                 spec: Some(PodSpec {
                     containers: vec![Container {
                         name: "delete-zeebe".to_string(),
-                        image: Some("busybox:latest".to_string()),
+                        image: Some(settings.cleanup_image.clone()),
                         command: Some(vec![
                             "/bin/sh".to_string(),
                             "-c".to_string(),
-                            "rm -rf /usr/local/zeebe/data/*".to_string(),
+                            format!("rm -rf {}/*", settings.zeebe_data_path),
                         ]),
                         volume_mounts: Some(vec![VolumeMount {
                             name: "data".to_string(),
-                            mount_path: "/usr/local/zeebe/data".to_string(),
+                            mount_path: settings.zeebe_data_path.clone(),
                             ..Default::default()
                         }]),
                         ..Default::default()
@@ -145,6 +321,7 @@ fn zeebe_data_deletion_job(pvc: &PersistentVolumeClaim) -> Job {
 }
 
 fn zeebe_data_restoration_job(
+    settings: &ComponentSettings,
     restore_binary: &str,
     restore_args: &[String],
     pvc: &PersistentVolumeClaim,
@@ -195,7 +372,7 @@ fn zeebe_data_restoration_job(
                         command: Some(command),
                         volume_mounts: Some(vec![VolumeMount {
                             name: "data".to_string(),
-                            mount_path: "/usr/local/zeebe/data".to_string(),
+                            mount_path: settings.zeebe_data_path.clone(),
                             ..Default::default()
                         }]),
                         env: Some(envs),
@@ -220,17 +397,18 @@ fn zeebe_data_restoration_job(
     }
 }
 
-#[tracing::instrument(skip(kube), err)]
-async fn delete_zeebe_data(kube: &kube::Client) -> Result<(), Box<dyn std::error::Error>> {
-    let jobs: Api<Job> = Api::default_namespaced(kube.clone());
-    let pvcs: Api<PersistentVolumeClaim> = Api::default_namespaced(kube.clone());
+#[tracing::instrument(skip(cluster), err)]
+async fn delete_zeebe_data(cluster: &Cluster, run_id: &str) -> Result<(), Error> {
+    let jobs: Api<Job> = Api::default_namespaced(cluster.kube.clone());
+    let pvcs: Api<PersistentVolumeClaim> = Api::default_namespaced(cluster.kube.clone());
+    let settings = &cluster.settings;
     let zeebe_pvcs = pvcs
-        .list(&ListParams::default().labels("app.kubernetes.io/component=zeebe-broker"))
+        .list(&ListParams::default().labels(&settings.zeebe_broker_selector))
         .await?;
 
     for pvc in &zeebe_pvcs {
         let pvc_name = pvc.metadata.name.to_owned().expect("PVC must have a name");
-        let job = zeebe_data_deletion_job(pvc);
+        let job = zeebe_data_deletion_job(settings, pvc);
         jobs.create(&PostParams::default(), &job).await?;
         info!("Deleting data of {}", pvc_name)
     }
@@ -241,52 +419,67 @@ async fn delete_zeebe_data(kube: &kube::Client) -> Result<(), Box<dyn std::error
         await_condition(jobs.clone(), &job_name, conditions::is_job_completed()).await?;
         jobs.delete(&job_name, &DeleteParams::background()).await?;
         info!("Deleted data of {}", pvc_name);
+        events::publish(
+            cluster,
+            pvc,
+            EventType::Normal,
+            "Restore",
+            "DataDeleted",
+            format!("Restore {} deleted the Zeebe data", run_id),
+        )
+        .await;
     }
     Ok(())
 }
 
-#[tracing::instrument(skip(kube), err)]
+#[tracing::instrument(skip(cluster), err)]
 async fn restore_zeebe_data_es(
-    kube: &kube::Client,
+    cluster: &Cluster,
     backup: &Backup,
-) -> Result<(), Box<dyn std::error::Error>> {
+    run_id: &str,
+) -> Result<(), Error> {
     let restore_args = vec![format!("--backupId={}", backup.id)];
-    restore_zeebe_data_with_args(kube, "/usr/local/zeebe/bin/restore", &restore_args).await
+    let restore_binary = &cluster.settings.zeebe_restore_binary;
+    restore_zeebe_data_with_args(cluster, restore_binary, &restore_args, run_id).await
 }
 
-#[tracing::instrument(skip(kube), err)]
+#[tracing::instrument(skip(cluster), err)]
 async fn restore_zeebe_data_rdbms(
-    kube: &kube::Client,
+    cluster: &Cluster,
     target: &RestoreTarget,
-) -> Result<(), Box<dyn std::error::Error>> {
+    run_id: &str,
+) -> Result<(), Error> {
     let restore_args = restore_args_for_target(target);
-    restore_zeebe_data_with_args(kube, "/usr/local/camunda/bin/restore", &restore_args).await
+    let restore_binary = &cluster.settings.camunda_restore_binary;
+    restore_zeebe_data_with_args(cluster, restore_binary, &restore_args, run_id).await
 }
 
 async fn restore_zeebe_data_with_args(
-    kube: &kube::Client,
+    cluster: &Cluster,
     restore_binary: &str,
     restore_args: &[String],
-) -> Result<(), Box<dyn std::error::Error>> {
-    let jobs: Api<Job> = Api::default_namespaced(kube.clone());
-    let sfs: Api<StatefulSet> = Api::default_namespaced(kube.clone());
+    run_id: &str,
+) -> Result<(), Error> {
+    let jobs: Api<Job> = Api::default_namespaced(cluster.kube.clone());
+    let sfs: Api<StatefulSet> = Api::default_namespaced(cluster.kube.clone());
+    let settings = &cluster.settings;
 
     let zeebe = {
         let sfs = sfs
-            .list(&ListParams::default().labels("app.kubernetes.io/component=zeebe-broker"))
+            .list(&ListParams::default().labels(&settings.zeebe_broker_selector))
             .await?
             .items;
         sfs.first().expect("Zeebe StatefulSet must exist").clone()
     };
 
-    let pvcs: Api<PersistentVolumeClaim> = Api::default_namespaced(kube.clone());
+    let pvcs: Api<PersistentVolumeClaim> = Api::default_namespaced(cluster.kube.clone());
     let zeebe_pvcs = pvcs
-        .list(&ListParams::default().labels("app.kubernetes.io/component=zeebe-broker"))
+        .list(&ListParams::default().labels(&settings.zeebe_broker_selector))
         .await?;
 
     for pvc in &zeebe_pvcs {
         let pvc_name = pvc.metadata.name.to_owned().expect("PVC must have a name");
-        let job = zeebe_data_restoration_job(restore_binary, restore_args, pvc, &zeebe);
+        let job = zeebe_data_restoration_job(settings, restore_binary, restore_args, pvc, &zeebe);
         jobs.create(&PostParams::default(), &job).await?;
         info!("Restoring data of {}", pvc_name)
     }
@@ -296,46 +489,229 @@ async fn restore_zeebe_data_with_args(
         await_condition(jobs.clone(), &job_name, conditions::is_job_completed()).await?;
         jobs.delete(&job_name, &DeleteParams::background()).await?;
         info!("Restored data of {}", pvc_name);
+        events::publish(
+            cluster,
+            pvc,
+            EventType::Normal,
+            "Restore",
+            "DataRestored",
+            format!(
+                "Restore {} restored the Zeebe data with {}",
+                run_id,
+                restore_args.join(" ")
+            ),
+        )
+        .await;
     }
     Ok(())
 }
 
-#[tracing::instrument(skip(kube), err)]
-async fn delete_indices(kube: &kube::Client) -> Result<(), Box<dyn std::error::Error>> {
-    let indices = get_all_indices(kube).await?;
+/// Deletes all indices and data streams that are either contained in one of the backup's
+/// snapshots or match one of the Camunda index prefixes. Every other index is left untouched.
+#[tracing::instrument(skip(search, snapshot_indices), err)]
+async fn delete_indices(
+    search: &impl SearchClient,
+    snapshot_indices: &HashSet<String>,
+    index_prefixes: &[String],
+) -> Result<(), Error> {
+    let deletion = plan_deletion(
+        get_all_indices(search).await?,
+        &get_data_streams(search).await?,
+        &get_aliases(search).await?,
+        snapshot_indices,
+        index_prefixes,
+    )?;
 
-    for index in indices {
-        delete_index(kube, &index).await?;
+    delete_data_streams(search, &deletion.data_streams).await?;
+    bulk_delete_indices(search, &deletion.indices).await?;
+
+    let remaining: HashSet<String> = get_all_indices(search)
+        .await?
+        .into_iter()
+        .chain(
+            get_data_streams(search)
+                .await?
+                .into_iter()
+                .map(|ds| ds.name),
+        )
+        .collect();
+    let leftovers = deletion
+        .data_streams
+        .iter()
+        .chain(&deletion.indices)
+        .filter(|name| remaining.contains(*name))
+        .cloned()
+        .collect::<Vec<_>>();
+    if !leftovers.is_empty() {
+        return Err(format!(
+            "Indices still exist after deletion: {}",
+            leftovers.join(", ")
+        )
+        .into());
     }
     Ok(())
 }
 
-#[tracing::instrument(skip(kube), err)]
+#[derive(Debug, Default, PartialEq)]
+struct Deletion {
+    data_streams: Vec<String>,
+    indices: Vec<String>,
+}
+
+/// Decides which data streams and concrete indices are deleted. Data streams are deleted as a
+/// whole because their backing indices cannot be deleted individually. Aliases are never deleted
+/// directly, but an alias on a remaining index must not collide with a restored index.
+fn plan_deletion(
+    indices: Vec<String>,
+    data_streams: &[DataStream],
+    aliases: &[Alias],
+    snapshot_indices: &HashSet<String>,
+    index_prefixes: &[String],
+) -> Result<Deletion, Error> {
+    let mut deletion = Deletion::default();
+
+    for data_stream in data_streams {
+        let in_snapshot = data_stream
+            .indices
+            .iter()
+            .any(|index| snapshot_indices.contains(&index.index_name));
+        if in_snapshot || is_restorable_index(&data_stream.name, snapshot_indices, index_prefixes) {
+            deletion.data_streams.push(data_stream.name.clone());
+        } else {
+            info!(
+                "Skipping data stream {}, it is not owned by Camunda",
+                data_stream.name
+            );
+        }
+    }
+
+    let backing_indices: HashSet<&str> = data_streams
+        .iter()
+        .flat_map(|ds| ds.indices.iter().map(|index| index.index_name.as_str()))
+        .collect();
+    for index in indices {
+        if backing_indices.contains(index.as_str()) {
+            continue;
+        }
+        if is_restorable_index(&index, snapshot_indices, index_prefixes) {
+            deletion.indices.push(index);
+        } else {
+            info!("Skipping index {}, it is not owned by Camunda", index);
+        }
+    }
+
+    let deleted: HashSet<&str> = deletion.indices.iter().map(String::as_str).collect();
+    for alias in aliases {
+        if !deleted.contains(alias.index.as_str()) && snapshot_indices.contains(&alias.alias) {
+            return Err(format!(
+                "Alias {} of index {} conflicts with index {} from the backup",
+                alias.alias, alias.index, alias.alias
+            )
+            .into());
+        }
+    }
+
+    Ok(deletion)
+}
+
+/// Whether an index is replaced by the restore. System indices are never touched.
+fn is_restorable_index(
+    index: &str,
+    snapshot_indices: &HashSet<String>,
+    index_prefixes: &[String],
+) -> bool {
+    if index.starts_with('.') {
+        return false;
+    }
+    snapshot_indices.contains(index)
+        || index_prefixes
+            .iter()
+            .any(|prefix| index.starts_with(prefix.trim_end_matches('*')))
+}
+
+/// Restores all snapshots of the backup, at most `parallelism` at a time. The snapshots contain
+/// disjoint indices so the order does not matter.
+#[tracing::instrument(skip(search), err)]
 async fn restore_indices(
-    kube: &kube::Client,
+    search: &impl SearchClient,
     backup: &Backup,
-) -> Result<(), Box<dyn std::error::Error>> {
-    for snapshot in &backup.snapshots {
-        restore_snapshot(kube, snapshot).await?;
-        info!("Restored snapshot {}", snapshot);
+    parallelism: usize,
+    index_settings: &BTreeMap<String, String>,
+    timeout: Duration,
+) -> Result<(), Error> {
+    stream::iter(&backup.snapshots)
+        .map(|snapshot| async move {
+            restore_snapshot(search, snapshot, index_settings, timeout).await?;
+            info!("Restored snapshot {}", snapshot);
+            Ok::<_, Error>(())
+        })
+        .buffer_unordered(parallelism)
+        .try_collect()
+        .await
+}
+
+/// Puts back the settings that were overridden while restoring. The original value of each index
+/// is read from the restored templates index, settings without a recorded value are reset to
+/// their default.
+#[tracing::instrument(skip(search, prefixes, restored), err)]
+async fn reset_index_settings(
+    search: &impl SearchClient,
+    prefixes: &IndexPrefixes,
+    restored: &[String],
+    index_settings: &BTreeMap<String, String>,
+) -> Result<(), Error> {
+    let templates_index = prefixes.templates_index();
+    let recorded = if restored.contains(&templates_index) {
+        templates::recorded_index_settings(search, &templates_index).await?
+    } else {
+        warn!("Backup contains no recorded index settings");
+        HashMap::new()
+    };
+    let overridden = index_settings.keys().cloned().collect::<Vec<_>>();
+
+    let mut groups: BTreeMap<String, (serde_json::Map<String, serde_json::Value>, Vec<String>)> =
+        BTreeMap::new();
+    for index in restored {
+        let original = settings::original_settings(index, &recorded, &overridden);
+        let key = serde_json::Value::Object(original.clone()).to_string();
+        groups
+            .entry(key)
+            .or_insert_with(|| (original, Vec::new()))
+            .1
+            .push(index.clone());
+    }
+
+    for (original, indices) in groups.values() {
+        for (setting, value) in original {
+            if value.is_null() {
+                warn!(
+                    "No recorded value of {} for {} indices, resetting it to the default",
+                    setting,
+                    indices.len()
+                );
+            }
+        }
+        put_index_settings(search, indices, original).await?;
+        info!("Reset index settings of {} indices", indices.len());
     }
     Ok(())
 }
 
 struct RestartableApps {
-    deployments: HashMap<String, i32>,
-    statefulsets: HashMap<String, i32>,
+    deployments: Vec<(Deployment, i32)>,
+    statefulsets: Vec<(StatefulSet, i32)>,
 }
 
-#[tracing::instrument(skip(kube), err)]
-async fn shutdown_apps(kube: &kube::Client) -> Result<RestartableApps, Box<dyn std::error::Error>> {
-    let deploy: Api<Deployment> = Api::default_namespaced(kube.clone());
-    let sfs: Api<StatefulSet> = Api::default_namespaced(kube.clone());
+#[tracing::instrument(skip(cluster), err)]
+async fn shutdown_apps(cluster: &Cluster, run_id: &str) -> Result<RestartableApps, Error> {
+    let deploy: Api<Deployment> = Api::default_namespaced(cluster.kube.clone());
+    let sfs: Api<StatefulSet> = Api::default_namespaced(cluster.kube.clone());
+    let settings = &cluster.settings;
 
-    let deployments: HashMap<String, i32> = deploy
-        .list(&ListParams::default().labels("app.kubernetes.io/part-of=camunda-platform"))
+    let deployments: Vec<(Deployment, i32)> = deploy
+        .list(&ListParams::default().labels(&settings.camunda_selector))
         .await?
-        .iter()
+        .into_iter()
         .map(|deploy| {
             let replicas = deploy
                 .spec
@@ -343,20 +719,13 @@ async fn shutdown_apps(kube: &kube::Client) -> Result<RestartableApps, Box<dyn s
                 .expect("Deployment must have a spec")
                 .replicas
                 .expect("Deployment must have replicas configured");
-            (
-                deploy
-                    .metadata
-                    .name
-                    .to_owned()
-                    .expect("Deploymetn must have a name"),
-                replicas,
-            )
+            (deploy, replicas)
         })
         .collect();
-    let statefulsets: HashMap<String, i32> = sfs
-        .list(&ListParams::default().labels("app.kubernetes.io/part-of=camunda-platform"))
+    let statefulsets: Vec<(StatefulSet, i32)> = sfs
+        .list(&ListParams::default().labels(&settings.camunda_selector))
         .await?
-        .iter()
+        .into_iter()
         .map(|statefulset| {
             let replicas = statefulset
                 .spec
@@ -364,18 +733,13 @@ async fn shutdown_apps(kube: &kube::Client) -> Result<RestartableApps, Box<dyn s
                 .expect("StatefulSet must have a spec")
                 .replicas
                 .expect("StatefulSet must have replicas configured");
-            (
-                statefulset
-                    .metadata
-                    .name
-                    .to_owned()
-                    .expect("Deploymetn must have a name"),
-                replicas,
-            )
+            (statefulset, replicas)
         })
         .collect();
 
-    for name in deployments.keys() {
+    for (deployment, replicas) in &deployments {
+        let name = &deployment.name_any();
+        events::annotate::<Deployment>(cluster, name, events::RESTORING, Some(run_id)).await;
         deploy
             .patch_scale(
                 name,
@@ -384,9 +748,20 @@ async fn shutdown_apps(kube: &kube::Client) -> Result<RestartableApps, Box<dyn s
             )
             .await?;
         info!("Shut down {}", &name);
+        events::publish(
+            cluster,
+            deployment,
+            EventType::Warning,
+            "Restore",
+            "ScaledDown",
+            scaled_down_note(run_id, *replicas),
+        )
+        .await;
     }
 
-    for name in statefulsets.keys() {
+    for (statefulset, replicas) in &statefulsets {
+        let name = &statefulset.name_any();
+        events::annotate::<StatefulSet>(cluster, name, events::RESTORING, Some(run_id)).await;
         sfs.patch_scale(
             name,
             &PatchParams::default(),
@@ -394,6 +769,15 @@ async fn shutdown_apps(kube: &kube::Client) -> Result<RestartableApps, Box<dyn s
         )
         .await?;
         info!("Shut down {}", &name);
+        events::publish(
+            cluster,
+            statefulset,
+            EventType::Warning,
+            "Restore",
+            "ScaledDown",
+            scaled_down_note(run_id, *replicas),
+        )
+        .await;
     }
 
     Ok(RestartableApps {
@@ -402,16 +786,25 @@ async fn shutdown_apps(kube: &kube::Client) -> Result<RestartableApps, Box<dyn s
     })
 }
 
-#[tracing::instrument(skip(kube, restartable), err)]
+fn scaled_down_note(run_id: &str, replicas: i32) -> String {
+    format!(
+        "Restore {} scaled down from {} replicas to 0, they are scaled up again once the restore is done",
+        run_id, replicas
+    )
+}
+
+#[tracing::instrument(skip(cluster, restartable), err)]
 async fn start_apps(
-    kube: &kube::Client,
+    cluster: &Cluster,
     restartable: &RestartableApps,
-) -> Result<(), Box<dyn std::error::Error>> {
+    run_id: &str,
+) -> Result<(), Error> {
     info!("Starting apps");
 
-    let deploy: Api<Deployment> = Api::default_namespaced(kube.clone());
-    let sfs: Api<StatefulSet> = Api::default_namespaced(kube.clone());
-    for (name, replicas) in &restartable.deployments {
+    let deploy: Api<Deployment> = Api::default_namespaced(cluster.kube.clone());
+    let sfs: Api<StatefulSet> = Api::default_namespaced(cluster.kube.clone());
+    for (deployment, replicas) in &restartable.deployments {
+        let name = &deployment.name_any();
         deploy
             .patch_scale(
                 name,
@@ -420,9 +813,20 @@ async fn start_apps(
             )
             .await?;
         info!("Started {}", &name);
+        events::publish(
+            cluster,
+            deployment,
+            EventType::Normal,
+            "Restore",
+            "ScaledUp",
+            format!("Restore {} scaled up to {} replicas", run_id, replicas),
+        )
+        .await;
+        events::annotate::<Deployment>(cluster, name, events::RESTORING, None).await;
     }
 
-    for (name, replicas) in &restartable.statefulsets {
+    for (statefulset, replicas) in &restartable.statefulsets {
+        let name = &statefulset.name_any();
         sfs.patch_scale(
             name,
             &PatchParams::default(),
@@ -430,25 +834,68 @@ async fn start_apps(
         )
         .await?;
         info!("Started {}", &name);
+        events::publish(
+            cluster,
+            statefulset,
+            EventType::Normal,
+            "Restore",
+            "ScaledUp",
+            format!("Restore {} scaled up to {} replicas", run_id, replicas),
+        )
+        .await;
+        events::annotate::<StatefulSet>(cluster, name, events::RESTORING, None).await;
     }
 
     Ok(())
 }
 
-#[tracing::instrument(skip(kube), err)]
-async fn find_newest_backup(kube: &kube::Client) -> Result<Backup, Box<dyn std::error::Error>> {
-    let zeebe_backups = zeebe::list_backups(kube).await?;
-    let operate_backups = operate::list_backups(kube).await?;
-    let backup_id = list::find_most_recent_usable(&zeebe_backups, &operate_backups)
-        .ok_or("No usable backup found")?;
-    let zeebe_snapshot = format!("camunda_zeebe_records_{backup_id}");
+/// Finds the most recent backup that is usable or, if Operate no longer knows it, recorded in the
+/// catalog.
+#[tracing::instrument(skip(cluster, prefixes), err)]
+async fn find_newest_backup(
+    cluster: &Cluster,
+    prefixes: &IndexPrefixes,
+    catalog: &str,
+    label: Option<&LabelSelector>,
+) -> Result<Backup, Error> {
+    let mut zeebe_backups = zeebe::list_backups(cluster).await?;
+    let operate_backups = operate::list_backups(cluster).await?;
+    let catalog = match (catalog::read(cluster, catalog).await, label) {
+        (Ok(entries), _) => entries,
+        // Labels are only recorded in the catalog.
+        (Err(e), Some(_)) => return Err(e),
+        (Err(e), None) => {
+            warn!("Could not read the backup catalog {}: {}", catalog, e);
+            vec![]
+        }
+    };
+    if let Some(label) = label {
+        let labeled = list::labeled(&catalog, label);
+        zeebe_backups.retain(|b| labeled.contains(&b.backup_id));
+    }
+    let backup_id = list::find_most_recent_recoverable(&zeebe_backups, &operate_backups, &catalog)
+        .ok_or(Error::NoUsableBackup)?;
+    let entry = catalog.into_iter().find(|e| e.backup_id == backup_id);
+    let zeebe_snapshot = entry
+        .as_ref()
+        .and_then(|e| e.zeebe_records_snapshot.clone())
+        .unwrap_or_else(|| prefixes.zeebe_records_snapshot(backup_id));
 
-    let operate_snapshots = operate::query_backup(kube, backup_id)
-        .await?
-        .details
-        .iter()
-        .map(|d| d.snapshot_name.clone())
-        .collect::<Vec<String>>();
+    let operate_snapshots = match operate::query_backup(cluster, backup_id).await {
+        Ok(backup) => backup
+            .details
+            .iter()
+            .map(|d| d.snapshot_name.clone())
+            .collect::<Vec<String>>(),
+        Err(e) if e.is_not_found() => {
+            info!(
+                "Operate does not know backup {}, using the catalog",
+                backup_id
+            );
+            entry.map(|e| e.operate_snapshots).unwrap_or_default()
+        }
+        Err(e) => return Err(e),
+    };
 
     info!("Using backup {}", backup_id);
     Ok(Backup {
@@ -460,10 +907,26 @@ async fn find_newest_backup(kube: &kube::Client) -> Result<Backup, Box<dyn std::
     })
 }
 
+/// Restores the newest completed runtime backup with the label.
+#[tracing::instrument(skip(cluster), err)]
+async fn find_newest_runtime_backup(
+    cluster: &Cluster,
+    catalog: &str,
+    label: &LabelSelector,
+) -> Result<RestoreTarget, Error> {
+    let labeled = list::labeled(&catalog::read(cluster, catalog).await?, label);
+    let mut runtime_backups = zeebe::list_runtime_backups(cluster).await?;
+    runtime_backups.retain(|b| labeled.contains(&b.backup_id));
+    let id =
+        list::find_most_recent_runtime_backup(&runtime_backups).ok_or(Error::NoUsableBackup)?;
+    info!("Using backup {}", id);
+    Ok(RestoreTarget::RdbmsBackupId { id })
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::types::RestoreTarget;
+    use crate::{elasticsearch::BackingIndex, types::RestoreTarget};
 
     #[test]
     fn test_determine_restore_target_auto() {
@@ -471,6 +934,27 @@ mod tests {
         assert!(matches!(target, RestoreTarget::RdbmsAuto));
     }
 
+    #[test]
+    fn test_check_restore_args() {
+        assert!(check_restore_args(StorageMode::Rdbms, None, Some(1), None).is_ok());
+        assert!(
+            check_restore_args(StorageMode::Rdbms, Some("2024-05-01T00:00:00Z"), None, None)
+                .is_ok()
+        );
+        assert!(check_restore_args(StorageMode::Elasticsearch, None, None, None).is_ok());
+        assert!(check_restore_args(StorageMode::Elasticsearch, None, Some(1), None).is_err());
+        assert!(check_restore_args(
+            StorageMode::Opensearch,
+            Some("2024-05-01T00:00:00Z"),
+            None,
+            None
+        )
+        .is_err());
+        let label = "reason=pre-upgrade".parse::<LabelSelector>().unwrap();
+        assert!(check_restore_args(StorageMode::Rdbms, None, Some(1), Some(&label)).is_err());
+        assert!(check_restore_args(StorageMode::Opensearch, None, None, Some(&label)).is_ok());
+    }
+
     #[test]
     fn test_determine_restore_target_backup_id() {
         let target = determine_restore_target(None, Some(123)).unwrap();
@@ -492,6 +976,120 @@ mod tests {
         assert!(result.is_err());
     }
 
+    #[test]
+    fn test_is_restorable_index() {
+        let snapshot_indices = HashSet::from(["custom-operate-list-view".to_string()]);
+        let prefixes = IndexPrefixes::default().owned_prefixes();
+
+        assert!(is_restorable_index(
+            "operate-list-view-8.1.0_",
+            &snapshot_indices,
+            &prefixes
+        ));
+        assert!(is_restorable_index(
+            "zeebe-record_job_8.2.3_2023-05-04",
+            &snapshot_indices,
+            &prefixes
+        ));
+        assert!(is_restorable_index(
+            "custom-operate-list-view",
+            &snapshot_indices,
+            &prefixes
+        ));
+        assert!(!is_restorable_index(
+            "my-application-logs",
+            &snapshot_indices,
+            &prefixes
+        ));
+        assert!(!is_restorable_index(
+            ".security-7",
+            &snapshot_indices,
+            &prefixes
+        ));
+    }
+
+    #[test]
+    fn test_custom_zeebe_prefix_keeps_unrelated_indices() {
+        let prefixes = IndexPrefixes {
+            zeebe: "prod".into(),
+            ..Default::default()
+        }
+        .owned_prefixes();
+        let snapshot_indices = HashSet::new();
+
+        assert!(is_restorable_index(
+            "prod_job_8.2.3_2023-05-04",
+            &snapshot_indices,
+            &prefixes
+        ));
+        assert!(!is_restorable_index(
+            "production-logs",
+            &snapshot_indices,
+            &prefixes
+        ));
+    }
+
+    #[test]
+    fn test_plan_deletion() {
+        let snapshot_indices = HashSet::from(["operate-list-view-8.1.0_".to_string()]);
+        let prefixes = vec!["operate-".to_string(), "zeebe-record_".to_string()];
+        let data_streams = vec![
+            DataStream {
+                name: "zeebe-record_stream".into(),
+                indices: vec![BackingIndex {
+                    index_name: ".ds-zeebe-record_stream-000001".into(),
+                }],
+            },
+            DataStream {
+                name: "logs-app".into(),
+                indices: vec![BackingIndex {
+                    index_name: ".ds-logs-app-000001".into(),
+                }],
+            },
+        ];
+        let aliases = vec![Alias {
+            alias: "operate-alias".into(),
+            index: "my-application".into(),
+        }];
+
+        let deletion = plan_deletion(
+            vec![
+                "operate-list-view-8.1.0_".into(),
+                "my-application".into(),
+                ".ds-zeebe-record_stream-000001".into(),
+            ],
+            &data_streams,
+            &aliases,
+            &snapshot_indices,
+            &prefixes,
+        )
+        .unwrap();
+        assert_eq!(
+            deletion,
+            Deletion {
+                data_streams: vec!["zeebe-record_stream".into()],
+                indices: vec!["operate-list-view-8.1.0_".into()],
+            }
+        );
+    }
+
+    #[test]
+    fn test_plan_deletion_alias_conflict_is_error() {
+        let snapshot_indices = HashSet::from(["operate-list-view-8.1.0_".to_string()]);
+        let aliases = vec![Alias {
+            alias: "operate-list-view-8.1.0_".into(),
+            index: "my-application".into(),
+        }];
+        let result = plan_deletion(
+            vec!["my-application".into()],
+            &[],
+            &aliases,
+            &snapshot_indices,
+            &["operate-".to_string()],
+        );
+        assert!(result.is_err());
+    }
+
     #[test]
     fn test_restore_args_for_rdbms_auto() {
         let target = RestoreTarget::RdbmsAuto;
@@ -0,0 +1,136 @@
+use std::collections::HashMap;
+
+use serde_json::{Map, Value};
+
+use crate::templates::StoredIndexSettings;
+
+/// Settings of Camunda indices that are recorded when a backup is taken, so they can be put back
+/// after restoring with overrides.
+pub const RECORDED_SETTINGS: &[&str] = &["index.number_of_replicas", "index.refresh_interval"];
+
+/// Records the settings of every index owned by one of the prefixes as documents of the
+/// templates index. Unlike the snapshot metadata, which is limited to 1024 bytes, the index keeps
+/// the values of each index.
+pub fn stored_settings(
+    settings: &HashMap<String, HashMap<String, String>>,
+    prefixes: &[String],
+) -> Vec<StoredIndexSettings> {
+    let mut stored = settings
+        .iter()
+        .filter(|(index, _)| owning_prefix(index, prefixes).is_some())
+        .map(|(index, values)| StoredIndexSettings {
+            index: index.clone(),
+            settings: values
+                .iter()
+                .map(|(setting, value)| (setting.clone(), Value::from(value.as_str())))
+                .collect(),
+        })
+        .collect::<Vec<_>>();
+    stored.sort_by(|a, b| a.index.cmp(&b.index));
+    stored
+}
+
+/// Returns the settings that undo the `overridden` keys on the given index. Settings without a
+/// recorded value are reset to their default.
+pub fn original_settings(
+    index: &str,
+    recorded: &HashMap<String, Map<String, Value>>,
+    overridden: &[String],
+) -> Map<String, Value> {
+    let recorded = recorded.get(index);
+    overridden
+        .iter()
+        .map(|setting| {
+            let value = recorded
+                .and_then(|recorded| recorded.get(setting))
+                .cloned()
+                .unwrap_or(Value::Null);
+            (setting.clone(), value)
+        })
+        .collect()
+}
+
+/// The prefix that the index starts with. Prefixes end with their delimiter, see
+/// [`crate::prefixes::IndexPrefixes::owned_prefixes`].
+fn owning_prefix<'a>(index: &str, prefixes: &'a [String]) -> Option<&'a str> {
+    prefixes
+        .iter()
+        .map(|prefix| prefix.trim_end_matches('*'))
+        .find(|prefix| index.starts_with(prefix))
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    fn prefixes() -> Vec<String> {
+        vec!["zeebe-record_".into(), "operate-".into()]
+    }
+
+    #[test]
+    fn test_stored_settings_keeps_value_per_index() {
+        let settings = HashMap::from([
+            (
+                "operate-list-view-8.1.0_".to_string(),
+                HashMap::from([("index.number_of_replicas".to_string(), "1".to_string())]),
+            ),
+            (
+                "operate-user-1.2.0_".to_string(),
+                HashMap::from([("index.number_of_replicas".to_string(), "0".to_string())]),
+            ),
+            (
+                "my-application".to_string(),
+                HashMap::from([("index.number_of_replicas".to_string(), "2".to_string())]),
+            ),
+        ]);
+        assert_eq!(
+            stored_settings(&settings, &prefixes()),
+            vec![
+                StoredIndexSettings {
+                    index: "operate-list-view-8.1.0_".into(),
+                    settings: json!({"index.number_of_replicas": "1"})
+                        .as_object()
+                        .unwrap()
+                        .clone(),
+                },
+                StoredIndexSettings {
+                    index: "operate-user-1.2.0_".into(),
+                    settings: json!({"index.number_of_replicas": "0"})
+                        .as_object()
+                        .unwrap()
+                        .clone(),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_original_settings() {
+        let recorded = HashMap::from([(
+            "zeebe-record_job_8.2.3_2023-05-04".to_string(),
+            json!({"index.number_of_replicas": "1", "index.refresh_interval": "1s"})
+                .as_object()
+                .unwrap()
+                .clone(),
+        )]);
+        let overridden = vec![
+            "index.number_of_replicas".to_string(),
+            "index.translog.durability".to_string(),
+        ];
+
+        let settings =
+            original_settings("zeebe-record_job_8.2.3_2023-05-04", &recorded, &overridden);
+        assert_eq!(
+            Value::Object(settings),
+            json!({"index.number_of_replicas": "1", "index.translog.durability": null})
+        );
+
+        let settings = original_settings("operate-user-1.2.0_", &recorded, &overridden);
+        assert_eq!(
+            Value::Object(settings),
+            json!({"index.number_of_replicas": null, "index.translog.durability": null})
+        );
+    }
+}
@@ -0,0 +1,684 @@
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+use http_body_util::Full;
+use hyper::{header::CONTENT_TYPE, Request, StatusCode};
+use serde_json::{json, Map, Value};
+use tracing::{info, warn};
+
+use crate::{
+    common::Idempotent,
+    elasticsearch::{bulk_delete_indices, SearchClient},
+    error::Error,
+};
+
+/// Kinds of cluster-level configuration that Camunda indices depend on. Snapshots are taken
+/// without global state, so these are stored as documents in an index included in the Zeebe
+/// records snapshot instead.
+#[derive(
+    serde::Serialize, serde::Deserialize, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash,
+)]
+#[serde(rename_all = "snake_case")]
+pub enum TemplateKind {
+    LifecyclePolicy,
+    ComponentTemplate,
+    IndexTemplate,
+}
+
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone, PartialEq)]
+pub struct StoredTemplate {
+    pub kind: TemplateKind,
+    pub name: String,
+    pub body: Value,
+}
+
+/// Settings of a Camunda index when the backup was taken, stored next to the templates. They are
+/// put back after restoring with overrides, see [`crate::settings`].
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone, PartialEq)]
+pub struct StoredIndexSettings {
+    pub index: String,
+    pub settings: Map<String, Value>,
+}
+
+/// Documents of the templates index.
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone, PartialEq)]
+#[serde(untagged)]
+enum StoredDocument {
+    Template(StoredTemplate),
+    IndexSettings(StoredIndexSettings),
+}
+
+impl StoredDocument {
+    fn id(&self) -> String {
+        match self {
+            StoredDocument::Template(t) => format!("{:?}:{}", t.kind, t.name),
+            StoredDocument::IndexSettings(s) => format!("IndexSettings:{}", s.index),
+        }
+    }
+}
+
+#[derive(serde::Deserialize, Debug)]
+struct IndexTemplates {
+    index_templates: Vec<NamedIndexTemplate>,
+}
+
+#[derive(serde::Deserialize, Debug)]
+struct NamedIndexTemplate {
+    name: String,
+    index_template: Value,
+}
+
+#[derive(serde::Deserialize, Debug)]
+struct ComponentTemplates {
+    component_templates: Vec<NamedComponentTemplate>,
+}
+
+#[derive(serde::Deserialize, Debug)]
+struct NamedComponentTemplate {
+    name: String,
+    component_template: Value,
+}
+
+/// Writes the index templates matching one of the prefixes, the component templates they are
+/// composed of and the lifecycle policies of the matching indices into a fresh `index`, together
+/// with the recorded `index_settings`. Lifecycle policies are ILM policies on Elasticsearch and
+/// ISM policies on OpenSearch.
+#[tracing::instrument(skip(search, prefixes, index_settings), err)]
+pub async fn capture_templates(
+    search: &impl SearchClient,
+    prefixes: &[String],
+    index: &str,
+    index_settings: Vec<StoredIndexSettings>,
+) -> Result<(), Error> {
+    let index_templates: IndexTemplates = get_json(search, "/_index_template").await?;
+    let component_templates: ComponentTemplates = get_json(search, "/_component_template").await?;
+    let mut templates = select_templates(
+        index_templates.index_templates,
+        component_templates.component_templates,
+        prefixes,
+    );
+
+    let mut policies = search.get_lifecycle_policies().await?;
+    let names = lifecycle_policy_names(
+        &templates,
+        search.lifecycle_policy_settings(),
+        &policies,
+        prefixes,
+    );
+    for name in names {
+        match policies.remove(&name) {
+            Some(body) => templates.push(StoredTemplate {
+                kind: TemplateKind::LifecyclePolicy,
+                name,
+                body,
+            }),
+            None => warn!("Lifecycle policy {} does not exist", name),
+        }
+    }
+    let template_count = templates.len();
+    let settings_count = index_settings.len();
+    let documents = templates
+        .into_iter()
+        .map(StoredDocument::Template)
+        .chain(
+            index_settings
+                .into_iter()
+                .map(StoredDocument::IndexSettings),
+        )
+        .collect::<Vec<_>>();
+
+    bulk_delete_indices(search, &[index.to_string()]).await?;
+    let req = Request::builder()
+        .method("PUT")
+        .uri(format!("/{}", index))
+        .header(CONTENT_TYPE, "application/json")
+        .body(Full::from(
+            json!({
+                "settings": {"index.number_of_shards": 1},
+                "mappings": {
+                    "dynamic": false,
+                    "properties": {
+                        "id": {"type": "keyword"},
+                        "kind": {"type": "keyword"},
+                        "name": {"type": "keyword"},
+                        "index": {"type": "keyword"},
+                    },
+                },
+            })
+            .to_string(),
+        ))?;
+    match search.request(req).await {
+        // The index was deleted above, so it was created by a retry of this request whose
+        // first response got lost.
+        Err(err) if already_exists(&err) => {}
+        result => {
+            result?;
+        }
+    }
+
+    let req = Request::builder()
+        .method("POST")
+        .uri(format!("/{}/_bulk?refresh=true", index))
+        .extension(Idempotent)
+        .header(CONTENT_TYPE, "application/x-ndjson")
+        .body(Full::from(bulk_body(&documents)))?;
+    let resp: Value = serde_json::from_slice(&search.request(req).await?)?;
+    if resp["errors"].as_bool().unwrap_or(false) {
+        return Err(format!("Failed to store templates in {}: {}", index, resp).into());
+    }
+    info!(
+        "Stored {} templates and policies and the settings of {} indices in {}",
+        template_count, settings_count, index
+    );
+    Ok(())
+}
+
+fn already_exists(err: &Error) -> bool {
+    matches!(
+        err,
+        Error::Http { status, body }
+            if *status == StatusCode::BAD_REQUEST
+                && body.contains("resource_already_exists_exception")
+    )
+}
+
+/// Recreates the templates and policies stored in `index` that no longer exist. Existing ones
+/// are left untouched because the components update them on startup.
+#[tracing::instrument(skip(search), err)]
+pub async fn restore_missing_templates(
+    search: &impl SearchClient,
+    index: &str,
+) -> Result<(), Error> {
+    let stored = read_stored(search, index)
+        .await?
+        .into_iter()
+        .filter_map(|document| match document {
+            StoredDocument::Template(template) => Some(template),
+            StoredDocument::IndexSettings(_) => None,
+        })
+        .collect::<Vec<_>>();
+
+    let mut existing = HashSet::new();
+    let index_templates: IndexTemplates = get_json(search, "/_index_template").await?;
+    existing.extend(
+        index_templates
+            .index_templates
+            .into_iter()
+            .map(|t| (TemplateKind::IndexTemplate, t.name)),
+    );
+    let component_templates: ComponentTemplates = get_json(search, "/_component_template").await?;
+    existing.extend(
+        component_templates
+            .component_templates
+            .into_iter()
+            .map(|t| (TemplateKind::ComponentTemplate, t.name)),
+    );
+    if stored
+        .iter()
+        .any(|t| t.kind == TemplateKind::LifecyclePolicy)
+    {
+        let policies = search.get_lifecycle_policies().await?;
+        existing.extend(
+            policies
+                .into_keys()
+                .map(|name| (TemplateKind::LifecyclePolicy, name)),
+        );
+    }
+
+    for template in missing_templates(&stored, &existing) {
+        put_template(search, template).await?;
+        info!("Restored {:?} {}", template.kind, template.name);
+    }
+    Ok(())
+}
+
+async fn put_template(search: &impl SearchClient, template: &StoredTemplate) -> Result<(), Error> {
+    let path = match template.kind {
+        TemplateKind::LifecyclePolicy => {
+            return search
+                .put_lifecycle_policy(&template.name, &template.body)
+                .await
+        }
+        TemplateKind::ComponentTemplate => "/_component_template",
+        TemplateKind::IndexTemplate => "/_index_template",
+    };
+    let req = Request::builder()
+        .method("PUT")
+        .uri(format!("{}/{}", path, template.name))
+        .header(CONTENT_TYPE, "application/json")
+        .body(Full::from(template.body.to_string()))?;
+    search.request(req).await?;
+    Ok(())
+}
+
+/// Returns the index settings recorded in `index`, keyed by index name.
+#[tracing::instrument(skip(search), err, level = "debug")]
+pub async fn recorded_index_settings(
+    search: &impl SearchClient,
+    index: &str,
+) -> Result<HashMap<String, Map<String, Value>>, Error> {
+    Ok(read_stored(search, index)
+        .await?
+        .into_iter()
+        .filter_map(|document| match document {
+            StoredDocument::IndexSettings(s) => Some((s.index, s.settings)),
+            StoredDocument::Template(_) => None,
+        })
+        .collect())
+}
+
+/// Number of documents read from the templates index per request.
+const PAGE_SIZE: usize = 1000;
+
+/// Reads all documents of the templates index, page by page in the order of their ID.
+async fn read_stored(
+    search: &impl SearchClient,
+    index: &str,
+) -> Result<Vec<StoredDocument>, Error> {
+    #[derive(serde::Deserialize, Debug)]
+    struct SearchResponse {
+        hits: Hits,
+    }
+    #[derive(serde::Deserialize, Debug)]
+    struct Hits {
+        total: Total,
+        hits: Vec<Hit>,
+    }
+    #[derive(serde::Deserialize, Debug)]
+    struct Total {
+        value: usize,
+    }
+    #[derive(serde::Deserialize, Debug)]
+    struct Hit {
+        _source: StoredDocument,
+        #[serde(default)]
+        sort: Vec<Value>,
+    }
+
+    let mut documents = Vec::new();
+    let mut search_after = None;
+    loop {
+        let mut query = json!({
+            "size": PAGE_SIZE,
+            "track_total_hits": true,
+            "sort": [{"id": {"order": "asc", "unmapped_type": "keyword"}}],
+        });
+        if let Some(search_after) = search_after.take() {
+            query["search_after"] = search_after;
+        }
+        let req = Request::builder()
+            .method("POST")
+            .uri(format!("/{}/_search", index))
+            .extension(Idempotent)
+            .header(CONTENT_TYPE, "application/json")
+            .body(Full::from(query.to_string()))?;
+        let resp: SearchResponse = serde_json::from_slice(&search.request(req).await?)?;
+        let page_size = resp.hits.hits.len();
+        search_after = resp
+            .hits
+            .hits
+            .last()
+            .map(|hit| Value::from(hit.sort.clone()));
+        documents.extend(resp.hits.hits.into_iter().map(|hit| hit._source));
+        if page_size < PAGE_SIZE {
+            // Documents with the same sort value would be skipped between pages.
+            if documents.len() != resp.hits.total.value {
+                return Err(format!(
+                    "Read {} of {} documents from {}",
+                    documents.len(),
+                    resp.hits.total.value,
+                    index
+                )
+                .into());
+            }
+            return Ok(documents);
+        }
+    }
+}
+
+async fn get_json<T: serde::de::DeserializeOwned>(
+    search: &impl SearchClient,
+    uri: &str,
+) -> Result<T, Error> {
+    let req = Request::builder()
+        .method("GET")
+        .uri(uri)
+        .body(Full::default())?;
+    Ok(serde_json::from_slice(&search.request(req).await?)?)
+}
+
+fn select_templates(
+    index_templates: Vec<NamedIndexTemplate>,
+    component_templates: Vec<NamedComponentTemplate>,
+    prefixes: &[String],
+) -> Vec<StoredTemplate> {
+    let owned = |pattern: &str| is_owned_pattern(pattern, prefixes);
+    let index_templates = index_templates
+        .into_iter()
+        .filter(|t| {
+            t.index_template["index_patterns"]
+                .as_array()
+                .into_iter()
+                .flatten()
+                .filter_map(Value::as_str)
+                .any(owned)
+        })
+        .collect::<Vec<_>>();
+    let composed_of: HashSet<&str> = index_templates
+        .iter()
+        .flat_map(|t| {
+            t.index_template["composed_of"]
+                .as_array()
+                .into_iter()
+                .flatten()
+        })
+        .filter_map(Value::as_str)
+        .collect();
+
+    let components = component_templates
+        .into_iter()
+        .filter(|t| composed_of.contains(t.name.as_str()))
+        .map(|t| StoredTemplate {
+            kind: TemplateKind::ComponentTemplate,
+            name: t.name,
+            body: t.component_template,
+        })
+        .collect::<Vec<_>>();
+    components
+        .into_iter()
+        .chain(index_templates.into_iter().map(|t| StoredTemplate {
+            kind: TemplateKind::IndexTemplate,
+            name: t.name,
+            body: t.index_template,
+        }))
+        .collect()
+}
+
+fn is_owned_pattern(pattern: &str, prefixes: &[String]) -> bool {
+    prefixes
+        .iter()
+        .any(|prefix| pattern.starts_with(prefix.trim_end_matches('*')))
+}
+
+/// Names of the lifecycle policies that the templates assign to new indices through one of the
+/// `settings`, and of the policies that apply themselves to indices matching one of the prefixes
+/// through an ISM template.
+fn lifecycle_policy_names(
+    templates: &[StoredTemplate],
+    settings: &[&str],
+    policies: &BTreeMap<String, Value>,
+    prefixes: &[String],
+) -> Vec<String> {
+    let assigned = templates.iter().flat_map(|t| {
+        let template_settings = &t.body["template"]["settings"];
+        settings
+            .iter()
+            .filter_map(|setting| template_setting(template_settings, setting))
+    });
+    let self_applied = policies
+        .iter()
+        .filter(|(_, policy)| {
+            let ism_templates = match &policy["ism_template"] {
+                Value::Array(templates) => templates.iter().collect(),
+                template => vec![template],
+            };
+            ism_templates
+                .into_iter()
+                .flat_map(|t| t["index_patterns"].as_array().into_iter().flatten())
+                .filter_map(Value::as_str)
+                .any(|pattern| is_owned_pattern(pattern, prefixes))
+        })
+        .map(|(name, _)| name.as_str());
+    let mut names = assigned
+        .chain(self_applied)
+        .map(String::from)
+        .collect::<Vec<_>>();
+    names.sort();
+    names.dedup();
+    names
+}
+
+/// Looks up a setting such as `index.lifecycle.name` in the settings of a template, which may be
+/// flat or nested and may leave out the `index.` prefix.
+fn template_setting<'a>(settings: &'a Value, key: &str) -> Option<&'a str> {
+    let short = key.strip_prefix("index.").unwrap_or(key);
+    [key, short].into_iter().find_map(|key| {
+        settings[key].as_str().or_else(|| {
+            key.split('.')
+                .try_fold(settings, |value, part| value.get(part))
+                .and_then(Value::as_str)
+        })
+    })
+}
+
+fn bulk_body(documents: &[StoredDocument]) -> String {
+    documents
+        .iter()
+        .flat_map(|document| {
+            let id = document.id();
+            let action = json!({"index": {"_id": id}});
+            let mut source = serde_json::to_value(document).expect("Document must be serializable");
+            source["id"] = Value::from(id);
+            [action.to_string(), source.to_string()]
+        })
+        .map(|line| line + "\n")
+        .collect()
+}
+
+/// Templates that do not exist, ordered so that dependencies are created first.
+fn missing_templates<'a>(
+    stored: &'a [StoredTemplate],
+    existing: &HashSet<(TemplateKind, String)>,
+) -> Vec<&'a StoredTemplate> {
+    let mut missing = stored
+        .iter()
+        .filter(|t| !existing.contains(&(t.kind, t.name.clone())))
+        .collect::<Vec<_>>();
+    missing.sort_by_key(|t| t.kind);
+    missing
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn index_template(name: &str, patterns: &[&str], composed_of: &[&str]) -> NamedIndexTemplate {
+        NamedIndexTemplate {
+            name: name.into(),
+            index_template: json!({
+                "index_patterns": patterns,
+                "composed_of": composed_of,
+                "template": {"settings": {"index": {"lifecycle": {"name": "zeebe-record-retention-policy"}}}},
+            }),
+        }
+    }
+
+    #[test]
+    fn test_select_templates() {
+        let templates = select_templates(
+            vec![
+                index_template(
+                    "zeebe-record_job_8.2.3",
+                    &["zeebe-record_job_8.2.3_*"],
+                    &["zeebe-record"],
+                ),
+                index_template("logs", &["logs-*"], &["logs-mappings"]),
+                index_template("zeebe-recordings", &["zeebe-recordings-*"], &[]),
+            ],
+            vec![
+                NamedComponentTemplate {
+                    name: "zeebe-record".into(),
+                    component_template: json!({"template": {}}),
+                },
+                NamedComponentTemplate {
+                    name: "logs-mappings".into(),
+                    component_template: json!({"template": {}}),
+                },
+            ],
+            &["zeebe-record_".to_string(), "operate-".to_string()],
+        );
+        assert_eq!(
+            templates
+                .iter()
+                .map(|t| (t.kind, t.name.as_str()))
+                .collect::<Vec<_>>(),
+            vec![
+                (TemplateKind::ComponentTemplate, "zeebe-record"),
+                (TemplateKind::IndexTemplate, "zeebe-record_job_8.2.3"),
+            ]
+        );
+        assert_eq!(
+            lifecycle_policy_names(&templates, &["index.lifecycle.name"], &BTreeMap::new(), &[]),
+            vec!["zeebe-record-retention-policy"]
+        );
+    }
+
+    #[test]
+    fn test_ism_policy_names() {
+        let templates = vec![StoredTemplate {
+            kind: TemplateKind::IndexTemplate,
+            name: "operate-list-view-8.3.0_template".into(),
+            body: json!({"template": {"settings": {
+                "plugins.index_state_management.policy_id": "operate_delete_archived_indices"
+            }}}),
+        }];
+        let policies = BTreeMap::from([
+            (
+                "zeebe-record-retention-policy".to_string(),
+                json!({"ism_template": [{"index_patterns": ["zeebe-record_*"]}]}),
+            ),
+            (
+                "logs-retention".to_string(),
+                json!({"ism_template": {"index_patterns": ["logs-*"]}}),
+            ),
+        ]);
+        assert_eq!(
+            lifecycle_policy_names(
+                &templates,
+                &[
+                    "index.plugins.index_state_management.policy_id",
+                    "index.opendistro.index_state_management.policy_id"
+                ],
+                &policies,
+                &["zeebe-record_".to_string(), "operate-".to_string()]
+            ),
+            vec![
+                "operate_delete_archived_indices",
+                "zeebe-record-retention-policy"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_template_setting() {
+        let nested = json!({"index": {"lifecycle": {"name": "nested"}}});
+        let flat = json!({"index.lifecycle.name": "flat"});
+        let short = json!({"lifecycle": {"name": "short"}});
+        assert_eq!(
+            template_setting(&nested, "index.lifecycle.name"),
+            Some("nested")
+        );
+        assert_eq!(
+            template_setting(&flat, "index.lifecycle.name"),
+            Some("flat")
+        );
+        assert_eq!(
+            template_setting(&short, "index.lifecycle.name"),
+            Some("short")
+        );
+        assert_eq!(template_setting(&json!({}), "index.lifecycle.name"), None);
+    }
+
+    #[test]
+    fn test_missing_templates_are_ordered_by_dependency() {
+        let stored = vec![
+            StoredTemplate {
+                kind: TemplateKind::IndexTemplate,
+                name: "operate-list-view-8.3.0_template".into(),
+                body: json!({}),
+            },
+            StoredTemplate {
+                kind: TemplateKind::ComponentTemplate,
+                name: "operate_template".into(),
+                body: json!({}),
+            },
+            StoredTemplate {
+                kind: TemplateKind::LifecyclePolicy,
+                name: "operate_delete_archived_indices".into(),
+                body: json!({"phases": {}}),
+            },
+        ];
+        let existing =
+            HashSet::from([(TemplateKind::ComponentTemplate, "operate_template".into())]);
+        let missing = missing_templates(&stored, &existing);
+        assert_eq!(
+            missing.iter().map(|t| t.kind).collect::<Vec<_>>(),
+            vec![TemplateKind::LifecyclePolicy, TemplateKind::IndexTemplate]
+        );
+        assert_eq!(missing[0].name, "operate_delete_archived_indices");
+    }
+
+    #[test]
+    fn test_bulk_body() {
+        let body = bulk_body(&[
+            StoredDocument::Template(StoredTemplate {
+                kind: TemplateKind::ComponentTemplate,
+                name: "zeebe-record".into(),
+                body: json!({"template": {}}),
+            }),
+            StoredDocument::IndexSettings(StoredIndexSettings {
+                index: "operate-list-view-8.3.0_".into(),
+                settings: json!({"index.number_of_replicas": "1"})
+                    .as_object()
+                    .unwrap()
+                    .clone(),
+            }),
+        ]);
+        assert_eq!(
+            body,
+            concat!(
+                r#"{"index":{"_id":"ComponentTemplate:zeebe-record"}}"#,
+                "\n",
+                r#"{"body":{"template":{}},"id":"ComponentTemplate:zeebe-record","kind":"component_template","name":"zeebe-record"}"#,
+                "\n",
+                r#"{"index":{"_id":"IndexSettings:operate-list-view-8.3.0_"}}"#,
+                "\n",
+                r#"{"id":"IndexSettings:operate-list-view-8.3.0_","index":"operate-list-view-8.3.0_","settings":{"index.number_of_replicas":"1"}}"#,
+                "\n",
+            )
+        );
+    }
+
+    #[test]
+    fn test_already_exists() {
+        let http = |status, body: &str| Error::Http {
+            status,
+            body: body.into(),
+        };
+        assert!(already_exists(&http(
+            StatusCode::BAD_REQUEST,
+            r#"{"error":{"type":"resource_already_exists_exception"},"status":400}"#
+        )));
+        assert!(!already_exists(&http(
+            StatusCode::BAD_REQUEST,
+            r#"{"error":{"type":"illegal_argument_exception"},"status":400}"#
+        )));
+        assert!(!already_exists(&http(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "resource_already_exists_exception"
+        )));
+    }
+
+    #[test]
+    fn test_stored_document_deserialize() {
+        let template: StoredDocument = serde_json::from_str(
+            r#"{"id":"IndexTemplate:x","kind":"index_template","name":"x","body":{}}"#,
+        )
+        .unwrap();
+        assert!(matches!(template, StoredDocument::Template(_)));
+        let settings: StoredDocument = serde_json::from_str(
+            r#"{"id":"IndexSettings:y","index":"y","settings":{"index.refresh_interval":"1s"}}"#,
+        )
+        .unwrap();
+        assert!(matches!(settings, StoredDocument::IndexSettings(_)));
+    }
+}
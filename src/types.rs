@@ -1,13 +1,401 @@
+use std::collections::BTreeMap;
+
 use serde::{Deserialize, Serialize};
 
 // --- StorageMode enum for CLI ---
 
-#[derive(Clone, Copy, Debug, clap::ValueEnum)]
+#[derive(Clone, Copy, Debug, Default, clap::ValueEnum)]
 pub enum StorageMode {
+    #[default]
     Elasticsearch,
+    Opensearch,
     Rdbms,
 }
 
+// --- Index prefix overrides for CLI ---
+
+#[derive(clap::Args, Clone, Debug, Default)]
+pub struct IndexPrefixArgs {
+    /// Index prefix of the Zeebe Elasticsearch exporter [default: discovered or zeebe-record]
+    #[arg(long, global = true)]
+    pub zeebe_index_prefix: Option<String>,
+    /// Index prefix of Operate [default: discovered or operate]
+    #[arg(long, global = true)]
+    pub operate_index_prefix: Option<String>,
+    /// Index prefix of Tasklist [default: discovered or tasklist]
+    #[arg(long, global = true)]
+    pub tasklist_index_prefix: Option<String>,
+    /// Index prefix of Optimize [default: optimize]
+    #[arg(long, global = true)]
+    pub optimize_index_prefix: Option<String>,
+}
+
+// --- Retry options for CLI ---
+
+#[derive(clap::Args, Clone, Debug)]
+pub struct RetryArgs {
+    /// Maximum number of attempts of idempotent requests to Camunda components and secondary storage
+    #[arg(long, default_value = "5")]
+    pub retry_attempts: std::num::NonZeroU32,
+    /// Delay before the first retry in milliseconds, doubled with every further attempt
+    #[arg(long, default_value = "500")]
+    pub retry_initial_backoff_ms: u64,
+    /// Maximum delay between retries in milliseconds
+    #[arg(long, default_value = "30000")]
+    pub retry_max_backoff_ms: u64,
+}
+
+// --- Elasticsearch / OpenSearch options for CLI ---
+
+#[derive(clap::Args, Clone, Default)]
+pub struct SearchArgs {
+    /// Snapshot repository to use, required if more than one repository is registered
+    #[arg(long, global = true)]
+    pub snapshot_repository: Option<String>,
+    /// URL of an external Elasticsearch or OpenSearch cluster, port-forwards into the cluster if unset
+    #[arg(long, global = true, env = "C8_BACKUP_SEARCH_URL")]
+    pub search_url: Option<String>,
+    /// Username for basic authentication against the external cluster
+    #[arg(long, global = true, env = "C8_BACKUP_SEARCH_USERNAME")]
+    pub search_username: Option<String>,
+    /// Password for basic authentication against the external cluster
+    #[arg(
+        long,
+        global = true,
+        env = "C8_BACKUP_SEARCH_PASSWORD",
+        hide_env_values = true
+    )]
+    pub search_password: Option<String>,
+    /// Encoded API key for the external cluster
+    #[arg(
+        long,
+        global = true,
+        env = "C8_BACKUP_SEARCH_API_KEY",
+        hide_env_values = true
+    )]
+    pub search_api_key: Option<String>,
+    /// Bearer token for the external cluster
+    #[arg(
+        long,
+        global = true,
+        env = "C8_BACKUP_SEARCH_TOKEN",
+        hide_env_values = true
+    )]
+    pub search_token: Option<String>,
+    /// Kubernetes Secret holding `username` and `password`, `api-key` or `token` for the external cluster
+    #[arg(long, global = true)]
+    pub search_credentials_secret: Option<String>,
+    /// PEM bundle of additional CA certificates to trust
+    #[arg(long, global = true)]
+    pub search_ca_cert: Option<std::path::PathBuf>,
+    /// PEM client certificate for mutual TLS
+    #[arg(long, global = true, requires = "search_client_key")]
+    pub search_client_cert: Option<std::path::PathBuf>,
+    /// PEM private key of the client certificate
+    #[arg(long, global = true, requires = "search_client_cert")]
+    pub search_client_key: Option<std::path::PathBuf>,
+}
+
+/// Shows whether credentials are set without revealing them.
+impl std::fmt::Debug for SearchArgs {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let redacted = |secret: &Option<String>| secret.as_ref().map(|_| "<redacted>");
+        f.debug_struct("SearchArgs")
+            .field("snapshot_repository", &self.snapshot_repository)
+            .field("search_url", &self.search_url)
+            .field("search_username", &self.search_username)
+            .field("search_password", &redacted(&self.search_password))
+            .field("search_api_key", &redacted(&self.search_api_key))
+            .field("search_token", &redacted(&self.search_token))
+            .field("search_credentials_secret", &self.search_credentials_secret)
+            .field("search_ca_cert", &self.search_ca_cert)
+            .field("search_client_cert", &self.search_client_cert)
+            .field("search_client_key", &self.search_client_key)
+            .finish()
+    }
+}
+
+// --- Catalog options for CLI ---
+
+#[derive(clap::Args, Clone, Debug)]
+pub struct CatalogArgs {
+    /// ConfigMap that records every created backup, used by `list` and `restore` when components
+    /// no longer know a backup
+    #[arg(long = "catalog", global = true, value_name = "CONFIGMAP", default_value = CatalogArgs::DEFAULT_CONFIGMAP)]
+    pub catalog_configmap: String,
+}
+
+impl CatalogArgs {
+    pub const DEFAULT_CONFIGMAP: &'static str = "c8-backup-catalog";
+}
+
+impl Default for CatalogArgs {
+    fn default() -> Self {
+        Self {
+            catalog_configmap: Self::DEFAULT_CONFIGMAP.to_string(),
+        }
+    }
+}
+
+// --- Notification options for CLI ---
+
+#[derive(clap::Args, Clone, Debug, Default)]
+pub struct NotifyArgs {
+    /// URL to post a JSON notification to when an operation starts, succeeds or fails
+    #[arg(
+        long = "webhook",
+        global = true,
+        value_name = "URL",
+        env = "C8_BACKUP_WEBHOOK",
+        hide_env_values = true
+    )]
+    pub webhooks: Vec<String>,
+    /// Slack incoming webhook URL to notify about operations
+    #[arg(
+        long = "slack-webhook",
+        global = true,
+        value_name = "URL",
+        env = "C8_BACKUP_SLACK_WEBHOOK",
+        hide_env_values = true
+    )]
+    pub slack_webhooks: Vec<String>,
+    /// Microsoft Teams incoming webhook URL to notify about operations
+    #[arg(
+        long = "teams-webhook",
+        global = true,
+        value_name = "URL",
+        env = "C8_BACKUP_TEAMS_WEBHOOK",
+        hide_env_values = true
+    )]
+    pub teams_webhooks: Vec<String>,
+}
+
+// --- Index restore options for CLI ---
+
+#[derive(clap::Args, Clone, Debug)]
+pub struct IndexRestoreArgs {
+    /// Additional prefix of indices that may be deleted before restoring (Elasticsearch and OpenSearch mode only)
+    #[arg(long = "index-prefix")]
+    pub extra_index_prefixes: Vec<String>,
+    /// Number of snapshots that are restored concurrently
+    #[arg(long, default_value = "4")]
+    pub restore_parallelism: std::num::NonZeroUsize,
+    /// Maximum time to wait for the shards of a snapshot to be recovered, e.g. `6h`
+    #[arg(long, value_parser = humantime::parse_duration, default_value = "6h")]
+    pub recovery_timeout: std::time::Duration,
+    /// Continue once the restored indices are yellow instead of waiting for green
+    #[arg(long)]
+    pub allow_yellow: bool,
+    /// Maximum time to wait for the restored indices to become green, or yellow with
+    /// `--allow-yellow`, e.g. `30m`
+    #[arg(long, value_parser = humantime::parse_duration, default_value = "30m")]
+    pub health_timeout: std::time::Duration,
+    /// Index setting applied while restoring, e.g. `number_of_replicas=0`. The recorded original
+    /// values are put back once the restore is done
+    #[arg(long = "restore-index-setting", value_name = "KEY=VALUE", value_parser = parse_index_setting)]
+    pub index_settings: Vec<(String, String)>,
+}
+
+/// Parses `KEY=VALUE`, adding the `index.` prefix to the key if it is missing.
+fn parse_index_setting(s: &str) -> Result<(String, String), String> {
+    let (key, value) = s
+        .split_once('=')
+        .ok_or(format!("invalid KEY=VALUE: no `=` found in `{}`", s))?;
+    let key = key.trim();
+    if key.is_empty() {
+        return Err(format!("invalid KEY=VALUE: empty key in `{}`", s));
+    }
+    let key = if key.starts_with("index.") {
+        key.to_string()
+    } else {
+        format!("index.{}", key)
+    };
+    Ok((key, value.trim().to_string()))
+}
+
+// --- Create options for CLI ---
+
+#[derive(clap::Args, Clone, Debug, Default)]
+pub struct CreateArgs {
+    /// Label of the backup, e.g. `reason=pre-upgrade`. Recorded in the catalog and in the snapshot
+    /// metadata
+    #[arg(long = "label", value_name = "KEY=VALUE", value_parser = parse_label)]
+    pub labels: Vec<(String, String)>,
+    /// Free-form note about the backup, recorded in the catalog and in the snapshot metadata
+    #[arg(long)]
+    pub note: Option<String>,
+    /// Protect the backup from `delete` and pruning until it is unpinned
+    #[arg(long)]
+    pub pin: bool,
+    /// ID of the backup instead of one chosen by `--backup-id-strategy`
+    #[arg(long, conflicts_with = "backup_id_strategy")]
+    pub backup_id: Option<u64>,
+    /// How the ID of the backup is chosen
+    #[arg(long, value_enum, default_value_t = BackupIdStrategy::Seconds)]
+    pub backup_id_strategy: BackupIdStrategy,
+    /// Maximum time to wait for each component to complete its backup, e.g. `2h`
+    #[arg(long, value_parser = humantime::parse_duration, default_value = "2h")]
+    pub backup_timeout: std::time::Duration,
+}
+
+/// How `create` chooses the ID of a new backup. The most recent backup is the one with the highest
+/// ID, so a strategy should only be changed to one that yields higher IDs.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum BackupIdStrategy {
+    /// Seconds since the Unix epoch
+    #[default]
+    Seconds,
+    /// Milliseconds since the Unix epoch, for backups taken less than a second apart
+    Millis,
+    /// One more than the highest ID in use, but at least the seconds since the Unix epoch
+    Monotonic,
+}
+
+/// Parses `KEY=VALUE`, the value may be empty.
+fn parse_label(s: &str) -> Result<(String, String), String> {
+    let (key, value) = s
+        .split_once('=')
+        .ok_or(format!("invalid KEY=VALUE: no `=` found in `{}`", s))?;
+    let key = key.trim();
+    if key.is_empty() {
+        return Err(format!("invalid KEY=VALUE: empty key in `{}`", s));
+    }
+    Ok((key.to_string(), value.trim().to_string()))
+}
+
+/// Selects backups by label, either `KEY` for any value or `KEY=VALUE`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct LabelSelector {
+    pub key: String,
+    pub value: Option<String>,
+}
+
+impl LabelSelector {
+    pub fn matches(&self, labels: &BTreeMap<String, String>) -> bool {
+        match (labels.get(&self.key), &self.value) {
+            (Some(actual), Some(expected)) => actual == expected,
+            (Some(_), None) => true,
+            (None, _) => false,
+        }
+    }
+}
+
+impl std::str::FromStr for LabelSelector {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (key, value) = match s.split_once('=') {
+            Some((key, value)) => (key.trim(), Some(value.trim().to_string())),
+            None => (s.trim(), None),
+        };
+        if key.is_empty() {
+            return Err(format!("invalid label selector: empty key in `{}`", s));
+        }
+        Ok(Self {
+            key: key.to_string(),
+            value,
+        })
+    }
+}
+
+// --- Daemon options for CLI ---
+
+#[derive(clap::Args, Clone, Debug)]
+pub struct DaemonArgs {
+    /// Cron expression of when to create backups, e.g. `0 2 * * *`. A leading seconds field and a
+    /// trailing year field are optional
+    #[arg(long, value_parser = parse_schedule)]
+    pub schedule: cron::Schedule,
+    /// Number of usable backups to keep, older backups are deleted after each successful backup
+    #[arg(long)]
+    pub keep: Option<std::num::NonZeroUsize>,
+    #[command(flatten)]
+    pub create: CreateArgs,
+    /// Address to serve Prometheus metrics on at `/metrics`, e.g. `0.0.0.0:9090`
+    #[arg(long)]
+    pub metrics_address: Option<std::net::SocketAddr>,
+}
+
+/// Parses a cron expression, accepting the common five-field form by running at second 0.
+fn parse_schedule(s: &str) -> Result<cron::Schedule, String> {
+    let expression = if s.split_whitespace().count() == 5 {
+        format!("0 {}", s.trim())
+    } else {
+        s.to_string()
+    };
+    expression
+        .parse()
+        .map_err(|e| format!("invalid cron expression `{}`: {}", s, e))
+}
+
+// --- Component settings of a configuration file profile ---
+
+/// Where the components of a deployment are found and how they are operated on. The defaults
+/// match the Camunda Helm chart.
+#[derive(Deserialize, Clone, Debug, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case", default, deny_unknown_fields)]
+pub struct ComponentSettings {
+    /// Label selector of the Zeebe broker StatefulSet, its pods and its volume claims
+    pub zeebe_broker_selector: String,
+    /// Label selector of the Zeebe gateway pods, which serve the backup API
+    pub zeebe_gateway_selector: String,
+    pub operate_selector: String,
+    pub tasklist_selector: String,
+    /// Label selector of the Elasticsearch pods, unless `--search-url` is used
+    pub elasticsearch_selector: String,
+    /// Label selector of the OpenSearch pods, unless `--search-url` is used
+    pub opensearch_selector: String,
+    /// Label selector of all Deployments and StatefulSets that are stopped during a restore
+    pub camunda_selector: String,
+    /// Management port of the Zeebe gateway
+    pub zeebe_port: u16,
+    /// Management port of Operate
+    pub operate_port: u16,
+    /// HTTP port of Elasticsearch or OpenSearch pods
+    pub search_port: u16,
+    /// Mount path of the Zeebe data volume in the restore jobs
+    pub zeebe_data_path: String,
+    /// Restore binary of the Zeebe image (Elasticsearch and OpenSearch mode)
+    pub zeebe_restore_binary: String,
+    /// Restore binary of the Camunda image (RDBMS mode)
+    pub camunda_restore_binary: String,
+    /// Image of the jobs that delete Zeebe data before a restore
+    pub cleanup_image: String,
+    /// Interval between two checks of a running backup, snapshot or restore, e.g. `5s`
+    #[serde(deserialize_with = "deserialize_duration")]
+    pub poll_interval: std::time::Duration,
+}
+
+impl Default for ComponentSettings {
+    fn default() -> Self {
+        Self {
+            zeebe_broker_selector: "app.kubernetes.io/component=zeebe-broker".to_string(),
+            zeebe_gateway_selector: "app.kubernetes.io/component=zeebe-gateway".to_string(),
+            operate_selector: "app.kubernetes.io/component=operate".to_string(),
+            tasklist_selector: "app.kubernetes.io/component=tasklist".to_string(),
+            elasticsearch_selector: "app=elasticsearch-master".to_string(),
+            opensearch_selector: "app.kubernetes.io/name=opensearch".to_string(),
+            camunda_selector: "app.kubernetes.io/part-of=camunda-platform".to_string(),
+            zeebe_port: 9600,
+            operate_port: 8080,
+            search_port: 9200,
+            zeebe_data_path: "/usr/local/zeebe/data".to_string(),
+            zeebe_restore_binary: "/usr/local/zeebe/bin/restore".to_string(),
+            camunda_restore_binary: "/usr/local/camunda/bin/restore".to_string(),
+            cleanup_image: "busybox:latest".to_string(),
+            poll_interval: std::time::Duration::from_secs(5),
+        }
+    }
+}
+
+fn deserialize_duration<'de, D>(deserializer: D) -> Result<std::time::Duration, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    let s = String::deserialize(deserializer)?;
+    humantime::parse_duration(&s).map_err(serde::de::Error::custom)
+}
+
 // --- Existing types (unchanged) ---
 
 #[derive(Deserialize, Debug, PartialEq, Eq, Hash, Clone, Copy)]
@@ -29,8 +417,16 @@ pub struct BackupDescriptor<T> {
     pub details: Vec<T>,
 }
 
-#[derive(Deserialize, Debug)]
-pub struct ZeebeDetails {}
+#[derive(Deserialize, Debug, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct ZeebeDetails {
+    #[serde(default)]
+    pub partition_id: Option<u32>,
+    #[serde(default)]
+    pub checkpoint_position: Option<i64>,
+    #[serde(default)]
+    pub broker_version: Option<String>,
+}
 
 #[derive(Deserialize, Debug)]
 #[serde(rename_all = "camelCase")]
@@ -113,7 +509,7 @@ pub struct CheckpointState {
     pub ranges: Vec<serde_json::Value>,
 }
 
-// --- Internal restore target enum ---
+// --- Restore target ---
 
 #[derive(Debug)]
 pub enum RestoreTarget {
@@ -123,6 +519,142 @@ pub enum RestoreTarget {
     RdbmsPointInTime { to: String },
 }
 
+// --- Backup catalog ---
+
+/// Record of a created backup in the catalog ConfigMap.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct CatalogEntry {
+    pub backup_id: u64,
+    /// RFC 3339 timestamp of when the backup completed, unknown for backups that were recorded
+    /// after the fact, such as by `pin`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub created_at: Option<String>,
+    /// Version per component, e.g. `zeebe` and `operate`
+    #[serde(default)]
+    pub versions: BTreeMap<String, String>,
+    /// Snapshots of the Operate backup (Elasticsearch and OpenSearch mode)
+    #[serde(default)]
+    pub operate_snapshots: Vec<String>,
+    /// Snapshot of the exported Zeebe records (Elasticsearch and OpenSearch mode)
+    #[serde(default)]
+    pub zeebe_records_snapshot: Option<String>,
+    /// Checkpoint position of the Zeebe backup per partition
+    #[serde(default)]
+    pub checkpoint_positions: BTreeMap<u32, i64>,
+    pub duration_seconds: f64,
+    /// User or host that created the backup
+    #[serde(default)]
+    pub operator: Option<String>,
+    #[serde(default)]
+    pub labels: BTreeMap<String, String>,
+    #[serde(default)]
+    pub note: Option<String>,
+    /// Pinned backups are neither deleted nor pruned
+    #[serde(default)]
+    pub pinned: bool,
+    /// Placeholder that reserves the ID while the backup is taken, replaced by the complete entry
+    /// once the backup is done
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub reserved: bool,
+}
+
+// --- Results of the client API ---
+
+/// Backups known to the components, see [`crate::C8Backup::list`].
+#[derive(Debug, Default)]
+pub struct BackupList {
+    /// Zeebe backups (Elasticsearch and OpenSearch mode)
+    pub zeebe: Vec<BackupDescriptor<ZeebeDetails>>,
+    /// Operate backups (Elasticsearch and OpenSearch mode)
+    pub operate: Vec<BackupDescriptor<OperateDetails>>,
+    /// Runtime backups (RDBMS mode)
+    pub runtime: Vec<RuntimeBackupInfo>,
+    /// Backups recorded in the catalog
+    pub catalog: Vec<CatalogEntry>,
+    /// The most recent backup that is completed by all components
+    pub most_recent_usable: Option<u64>,
+}
+
+/// State of one backup in every component, see [`crate::C8Backup::describe`].
+#[derive(Debug)]
+pub struct BackupDescription {
+    pub backup_id: u64,
+    /// State of the Zeebe backup (Elasticsearch and OpenSearch mode), `None` if unknown to Zeebe
+    pub zeebe: Option<BackupState>,
+    /// State of the Operate backup (Elasticsearch and OpenSearch mode), `None` if unknown to Operate
+    pub operate: Option<BackupState>,
+    /// State of the runtime backup (RDBMS mode), `None` if unknown to Zeebe
+    pub runtime: Option<BackupState>,
+    /// Entry of the backup in the catalog
+    pub catalog: Option<CatalogEntry>,
+}
+
+/// A completed backup, see [`crate::C8Backup::create`].
+#[derive(Debug)]
+pub struct CreatedBackup {
+    pub backup_id: u64,
+    /// Snapshot of the exported Zeebe records (Elasticsearch and OpenSearch mode)
+    pub zeebe_records_snapshot: Option<String>,
+    pub duration: std::time::Duration,
+    /// How long Zeebe exporting was paused (Elasticsearch and OpenSearch mode)
+    pub exporting_paused: Option<std::time::Duration>,
+}
+
+/// A restored backup, see [`crate::C8Backup::restore`].
+#[derive(Debug)]
+pub struct RestoredBackup {
+    pub target: RestoreTarget,
+    /// Indices restored from snapshots (Elasticsearch and OpenSearch mode)
+    pub indices: Vec<String>,
+}
+
+/// A deleted backup, see [`crate::C8Backup::delete`].
+#[derive(Debug)]
+pub struct DeletedBackup {
+    pub backup_id: u64,
+    /// Snapshots that were deleted with the backup (Elasticsearch and OpenSearch mode)
+    pub snapshots: Vec<String>,
+}
+
+/// Status of a backup freshness check, see [`crate::C8Backup::check`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CheckStatus {
+    Ok,
+    Warning,
+    Critical,
+}
+
+impl CheckStatus {
+    /// Exit code of the status in the Nagios plugin convention.
+    pub fn exit_code(self) -> u8 {
+        match self {
+            CheckStatus::Ok => 0,
+            CheckStatus::Warning => 1,
+            CheckStatus::Critical => 2,
+        }
+    }
+}
+
+impl std::fmt::Display for CheckStatus {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CheckStatus::Ok => write!(f, "OK"),
+            CheckStatus::Warning => write!(f, "WARNING"),
+            CheckStatus::Critical => write!(f, "CRITICAL"),
+        }
+    }
+}
+
+/// Result of a backup freshness check, see [`crate::C8Backup::check`].
+#[derive(Debug)]
+pub struct BackupCheck {
+    pub status: CheckStatus,
+    /// One-line description of the status
+    pub summary: String,
+    pub most_recent_usable: Option<u64>,
+}
+
 // --- Request type for runtime backups ---
 
 #[derive(Serialize, Debug)]
@@ -135,6 +667,38 @@ pub struct TakeRuntimeBackupRequest {
 mod tests {
     use super::*;
 
+    #[test]
+    fn test_parse_schedule() {
+        let five_fields = parse_schedule("30 2 * * *").unwrap();
+        let six_fields = parse_schedule("0 30 2 * * *").unwrap();
+        assert!(five_fields.timeunitspec_eq(&six_fields));
+        assert!(parse_schedule("not a schedule").is_err());
+    }
+
+    #[test]
+    fn test_parse_label() {
+        assert_eq!(
+            parse_label("reason = pre-upgrade").unwrap(),
+            ("reason".to_string(), "pre-upgrade".to_string())
+        );
+        assert!(parse_label("pre-upgrade").is_err());
+        assert!(parse_label("=x").is_err());
+    }
+
+    #[test]
+    fn test_label_selector() {
+        let labels = BTreeMap::from([
+            ("pre-upgrade".to_string(), String::new()),
+            ("reason".to_string(), "migration".to_string()),
+        ]);
+        let selector = |s: &str| s.parse::<LabelSelector>().unwrap();
+        assert!(selector("pre-upgrade").matches(&labels));
+        assert!(selector("reason=migration").matches(&labels));
+        assert!(!selector("reason=upgrade").matches(&labels));
+        assert!(!selector("nightly").matches(&labels));
+        assert!("=x".parse::<LabelSelector>().is_err());
+    }
+
     #[test]
     fn test_backup_state_deserialize() {
         let cases = vec![
@@ -228,6 +792,46 @@ mod tests {
         assert_eq!(desc.backup_id, 123);
     }
 
+    #[test]
+    fn test_zeebe_backup_details_deserialize() {
+        let json = r#"{"backupId": 123, "state": "COMPLETED", "details": [{
+            "partitionId": 2,
+            "state": "COMPLETED",
+            "checkpointPosition": 42,
+            "brokerVersion": "8.5.0"
+        }]}"#;
+        let desc: BackupDescriptor<ZeebeDetails> = serde_json::from_str(json).unwrap();
+        assert_eq!(desc.details[0].partition_id, Some(2));
+        assert_eq!(desc.details[0].checkpoint_position, Some(42));
+        assert_eq!(desc.details[0].broker_version.as_deref(), Some("8.5.0"));
+    }
+
+    #[test]
+    fn test_catalog_entry_roundtrip() {
+        let entry = CatalogEntry {
+            backup_id: 1683214620,
+            created_at: Some("2023-05-04T15:37:00Z".to_string()),
+            versions: BTreeMap::from([("zeebe".to_string(), "8.5.0".to_string())]),
+            checkpoint_positions: BTreeMap::from([(1, 10), (2, 12)]),
+            duration_seconds: 61.5,
+            ..Default::default()
+        };
+        let json = serde_json::to_string(&entry).unwrap();
+        assert!(json.contains(r#""checkpointPositions":{"1":10,"2":12}"#));
+        assert_eq!(serde_json::from_str::<CatalogEntry>(&json).unwrap(), entry);
+    }
+
+    #[test]
+    fn test_catalog_entry_without_creation_time() {
+        let entry = CatalogEntry {
+            backup_id: 42,
+            ..Default::default()
+        };
+        let json = serde_json::to_string(&entry).unwrap();
+        assert!(!json.contains("createdAt"));
+        assert_eq!(serde_json::from_str::<CatalogEntry>(&json).unwrap(), entry);
+    }
+
     #[test]
     fn test_existing_operate_backup_descriptor_still_works() {
         let json =
@@ -242,4 +846,33 @@ mod tests {
         let json = serde_json::to_string(&req).unwrap();
         assert_eq!(json, r#"{"backupId":42}"#);
     }
+
+    #[test]
+    fn test_search_args_debug_redacts_credentials() {
+        let args = SearchArgs {
+            search_username: Some("elastic".to_string()),
+            search_password: Some("hunter2".to_string()),
+            search_api_key: Some("c2VjcmV0".to_string()),
+            ..Default::default()
+        };
+        let debug = format!("{:?}", args);
+        assert!(debug.contains("elastic"));
+        assert!(debug.contains("<redacted>"));
+        assert!(!debug.contains("hunter2"));
+        assert!(!debug.contains("c2VjcmV0"));
+    }
+
+    #[test]
+    fn test_parse_index_setting() {
+        assert_eq!(
+            parse_index_setting("number_of_replicas=0").unwrap(),
+            ("index.number_of_replicas".to_string(), "0".to_string())
+        );
+        assert_eq!(
+            parse_index_setting("index.refresh_interval=-1").unwrap(),
+            ("index.refresh_interval".to_string(), "-1".to_string())
+        );
+        assert!(parse_index_setting("number_of_replicas").is_err());
+        assert!(parse_index_setting("=0").is_err());
+    }
 }
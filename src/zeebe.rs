@@ -1,19 +1,18 @@
-use std::error::Error;
-
 use bytes::Bytes;
 use http_body_util::Full;
 use hyper::{header::CONTENT_TYPE, Request};
 
 use crate::{
-    common::make_component_request,
+    common::{make_component_request, Cluster, Idempotent},
+    error::Error,
     types::{
         BackupDescriptor, CheckpointState, RuntimeBackupInfo, TakeBackupRequest,
         TakeRuntimeBackupRequest, ZeebeDetails,
     },
 };
 
-#[tracing::instrument(skip(kube), err)]
-pub async fn take_backup(kube: &kube::Client, backup_id: u64) -> Result<(), Box<dyn Error>> {
+#[tracing::instrument(skip(cluster), err)]
+pub async fn take_backup(cluster: &Cluster, backup_id: u64) -> Result<(), Error> {
     let req = Request::builder()
         .method("POST")
         .uri("/actuator/backups")
@@ -24,73 +23,84 @@ pub async fn take_backup(kube: &kube::Client, backup_id: u64) -> Result<(), Box<
             })
             .expect("Request can be serialized"),
         ))?;
-    make_zeebe_request(kube, req).await?;
+    make_zeebe_request(cluster, req).await?;
     Ok(())
 }
 
-#[tracing::instrument(skip(kube), err, level = "debug")]
+#[tracing::instrument(skip(cluster), err, level = "debug")]
 pub async fn query_backup(
-    kube: &kube::Client,
+    cluster: &Cluster,
     backup_id: u64,
-) -> Result<BackupDescriptor<ZeebeDetails>, Box<dyn Error>> {
+) -> Result<BackupDescriptor<ZeebeDetails>, Error> {
     let req = Request::builder()
         .method("GET")
         .uri(format!("/actuator/backups/{}", backup_id))
         .body(Full::default())?;
 
-    let resp = make_zeebe_request(kube, req).await?;
+    let resp = make_zeebe_request(cluster, req).await?;
     Ok(serde_json::from_slice(&resp)?)
 }
 
-#[tracing::instrument(skip(kube), err, level = "debug")]
-pub async fn list_backups(
-    kube: &kube::Client,
-) -> Result<Vec<BackupDescriptor<ZeebeDetails>>, Box<dyn Error>> {
+#[tracing::instrument(skip(cluster), err, level = "debug")]
+pub async fn list_backups(cluster: &Cluster) -> Result<Vec<BackupDescriptor<ZeebeDetails>>, Error> {
     let req = Request::builder()
         .method("GET")
         .uri("/actuator/backups")
         .body(Full::default())?;
-    let resp = make_zeebe_request(kube, req).await?;
+    let resp = make_zeebe_request(cluster, req).await?;
 
     Ok(serde_json::from_slice(&resp)?)
 }
 
-#[tracing::instrument(skip(kube), err)]
-pub async fn pause_exporting(kube: &kube::Client) -> Result<(), Box<dyn Error>> {
+#[tracing::instrument(skip(cluster), err)]
+pub async fn delete_backup(cluster: &Cluster, backup_id: u64) -> Result<(), Error> {
+    let req = Request::builder()
+        .method("DELETE")
+        .uri(format!("/actuator/backups/{}", backup_id))
+        .body(Full::default())?;
+    make_zeebe_request(cluster, req).await?;
+    Ok(())
+}
+
+#[tracing::instrument(skip(cluster), err)]
+pub async fn pause_exporting(cluster: &Cluster) -> Result<(), Error> {
     let req = Request::builder()
         .method("POST")
         .uri("/actuator/exporting/pause")
+        .extension(Idempotent)
         .body(Full::default())?;
 
-    make_zeebe_request(kube, req).await?;
+    make_zeebe_request(cluster, req).await?;
     Ok(())
 }
 
-#[tracing::instrument(skip(kube), err)]
-pub async fn resume_exporting(kube: &kube::Client) -> Result<(), Box<dyn Error>> {
+#[tracing::instrument(skip(cluster), err)]
+pub async fn resume_exporting(cluster: &Cluster) -> Result<(), Error> {
     let req = Request::builder()
         .method("POST")
         .uri("/actuator/exporting/resume")
+        .extension(Idempotent)
         .body(Full::default())?;
 
-    make_zeebe_request(kube, req).await?;
+    make_zeebe_request(cluster, req).await?;
     Ok(())
 }
 
-async fn make_zeebe_request(
-    kube: &kube::Client,
-    req: Request<Full<Bytes>>,
-) -> Result<Bytes, Box<dyn std::error::Error>> {
-    make_component_request(kube, "app.kubernetes.io/component=zeebe-gateway", 9600, req).await
+async fn make_zeebe_request(cluster: &Cluster, req: Request<Full<Bytes>>) -> Result<Bytes, Error> {
+    let settings = &cluster.settings;
+    make_component_request(
+        cluster,
+        &settings.zeebe_gateway_selector,
+        settings.zeebe_port,
+        req,
+    )
+    .await
 }
 
 // --- RDBMS Runtime Backup API ---
 
-#[tracing::instrument(skip(kube), err)]
-pub async fn take_runtime_backup(
-    kube: &kube::Client,
-    backup_id: u64,
-) -> Result<(), Box<dyn Error>> {
+#[tracing::instrument(skip(cluster), err)]
+pub async fn take_runtime_backup(cluster: &Cluster, backup_id: u64) -> Result<(), Error> {
     let req = Request::builder()
         .method("POST")
         .uri("/actuator/backupRuntime")
@@ -99,41 +109,49 @@ pub async fn take_runtime_backup(
             serde_json::to_string(&TakeRuntimeBackupRequest { backup_id })
                 .expect("Request can be serialized"),
         ))?;
-    make_zeebe_request(kube, req).await?;
+    make_zeebe_request(cluster, req).await?;
     Ok(())
 }
 
-#[tracing::instrument(skip(kube), err, level = "debug")]
+#[tracing::instrument(skip(cluster), err, level = "debug")]
 pub async fn query_runtime_backup(
-    kube: &kube::Client,
+    cluster: &Cluster,
     backup_id: u64,
-) -> Result<RuntimeBackupInfo, Box<dyn Error>> {
+) -> Result<RuntimeBackupInfo, Error> {
     let req = Request::builder()
         .method("GET")
         .uri(format!("/actuator/backupRuntime/{}", backup_id))
         .body(Full::default())?;
-    let resp = make_zeebe_request(kube, req).await?;
+    let resp = make_zeebe_request(cluster, req).await?;
     Ok(serde_json::from_slice(&resp)?)
 }
 
-#[tracing::instrument(skip(kube), err, level = "debug")]
-pub async fn list_runtime_backups(
-    kube: &kube::Client,
-) -> Result<Vec<RuntimeBackupInfo>, Box<dyn Error>> {
+#[tracing::instrument(skip(cluster), err, level = "debug")]
+pub async fn list_runtime_backups(cluster: &Cluster) -> Result<Vec<RuntimeBackupInfo>, Error> {
     let req = Request::builder()
         .method("GET")
         .uri("/actuator/backupRuntime")
         .body(Full::default())?;
-    let resp = make_zeebe_request(kube, req).await?;
+    let resp = make_zeebe_request(cluster, req).await?;
     Ok(serde_json::from_slice(&resp)?)
 }
 
-#[tracing::instrument(skip(kube), err, level = "debug")]
-pub async fn get_backup_state(kube: &kube::Client) -> Result<CheckpointState, Box<dyn Error>> {
+#[tracing::instrument(skip(cluster), err)]
+pub async fn delete_runtime_backup(cluster: &Cluster, backup_id: u64) -> Result<(), Error> {
+    let req = Request::builder()
+        .method("DELETE")
+        .uri(format!("/actuator/backupRuntime/{}", backup_id))
+        .body(Full::default())?;
+    make_zeebe_request(cluster, req).await?;
+    Ok(())
+}
+
+#[tracing::instrument(skip(cluster), err, level = "debug")]
+pub async fn get_backup_state(cluster: &Cluster) -> Result<CheckpointState, Error> {
     let req = Request::builder()
         .method("GET")
         .uri("/actuator/backupRuntime/state")
         .body(Full::default())?;
-    let resp = make_zeebe_request(kube, req).await?;
+    let resp = make_zeebe_request(cluster, req).await?;
     Ok(serde_json::from_slice(&resp)?)
 }